@@ -0,0 +1,113 @@
+//! Compares the elimination and DLX backends on the same puzzles, both at
+//! finding a solution and at counting up to two of them. Run with
+//! `cargo bench`, optionally followed by `--` and the files to read, which
+//! default to the test data.
+
+extern crate sudokusolve;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use sudokusolve::dlx::DlxSolver;
+use sudokusolve::observer::{DummyGridObserver, DummySolverObserver};
+use sudokusolve::reader::{self, Puzzle};
+use sudokusolve::solver::{Backend, ObserveableGrid, Solver, SudokuSolver};
+use sudokusolve::MultiGrid;
+
+fn solver(backend: Backend, puzzle: &Puzzle) -> Box<dyn Solver> {
+    let rules = Rc::new(puzzle.rules());
+    let mut solver: Box<dyn Solver> = match (backend, &puzzle.layout) {
+        (Backend::Elimination, Some(layout)) => {
+            let grid = MultiGrid::new(DummyGridObserver {}, Rc::new(layout.clone()), rules);
+            Box::new(SudokuSolver::new(grid, DummySolverObserver {}))
+        }
+        (Backend::Elimination, None) => {
+            let grid = ObserveableGrid::with_rules(DummyGridObserver {}, rules);
+            Box::new(SudokuSolver::new(grid, DummySolverObserver {}))
+        }
+        (Backend::Dlx, Some(layout)) => Box::new(DlxSolver::with_layout(layout, rules)),
+        (Backend::Dlx, None) => Box::new(DlxSolver::new(rules)),
+    };
+    puzzle.apply(&mut *solver);
+    solver
+}
+
+/// Prints how long a backend took, with the guesses it made if known.
+fn report(backend: Backend, task: &str, elapsed: Duration, puzzles: usize, guesses: Option<usize>) {
+    print!(
+        "  {:<12} {:<6} {:>9.3} s {:>10.3} ms/puzzle",
+        format!("{:?}", backend).to_lowercase(),
+        task,
+        elapsed.as_secs_f64(),
+        elapsed.as_secs_f64() * 1000.0 / puzzles.max(1) as f64,
+    );
+    match guesses {
+        Some(guesses) => println!(" {:>10} guesses", guesses),
+        None => println!(),
+    }
+}
+
+fn main() {
+    // Cargo passes `--bench` along with the files.
+    let mut files: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect();
+    if files.is_empty() {
+        files = vec!["testdata/easy".to_string(), "testdata/hard".to_string()];
+    }
+    let backends = [Backend::Elimination, Backend::Dlx];
+    for filename in &files {
+        let input = match File::open(filename) {
+            Ok(file) => BufReader::new(file),
+            Err(err) => {
+                eprintln!("Failed to open file '{}', ({})", filename, err);
+                continue;
+            }
+        };
+        let format = reader::Format::from_extension(filename);
+        let puzzles: Vec<Puzzle> = reader::read(input, format).filter_map(Result::ok).collect();
+        println!("{}: {} puzzles", filename, puzzles.len());
+
+        let mut solutions = Vec::new();
+        for &backend in &backends {
+            let start = Instant::now();
+            let mut guesses = 0;
+            let outcomes: Vec<_> = puzzles
+                .iter()
+                .map(|puzzle| {
+                    let report = solver(backend, puzzle).solve();
+                    guesses += report.stats.guesses;
+                    report.outcome
+                })
+                .collect();
+            report(
+                backend,
+                "solve",
+                start.elapsed(),
+                puzzles.len(),
+                Some(guesses),
+            );
+            solutions.push(outcomes);
+        }
+        if solutions.windows(2).any(|pair| pair[0] != pair[1]) {
+            println!("  the backends found different solutions");
+        }
+
+        let mut counts = Vec::new();
+        for &backend in &backends {
+            let start = Instant::now();
+            let found: Vec<usize> = puzzles
+                .iter()
+                .map(|puzzle| solver(backend, puzzle).count_solutions(2))
+                .collect();
+            report(backend, "count", start.elapsed(), puzzles.len(), None);
+            counts.push(found);
+        }
+        if counts.windows(2).any(|pair| pair[0] != pair[1]) {
+            println!("  the backends counted different numbers of solutions");
+        }
+    }
+}
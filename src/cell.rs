@@ -1,31 +1,50 @@
 use bitmaps::Bitmap;
 
+use crate::house::Shape;
+
 #[derive(Debug)]
 pub struct EliminationError {}
 
 #[derive(Clone, Debug)]
 pub struct Cell {
-    possibles: Bitmap<9>,
+    possibles: Bitmap<32>,
+}
+
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell::new()
+    }
 }
 
 impl Cell {
     pub fn new() -> Cell {
+        Cell::with_digits(9)
+    }
+
+    /// A cell where the digits `1..=digits` are possible.
+    pub fn with_digits(digits: i32) -> Cell {
         Cell {
-            possibles: Bitmap::mask(9),
+            possibles: Bitmap::mask(digits as usize),
         }
     }
 
     pub fn set_hint(self: &mut Cell, hint: i32) {
+        assert!(
+            (1..=Shape::MAX_SIZE).contains(&hint),
+            "Hint out of range: {}",
+            hint
+        );
         self.possibles = Bitmap::mask(0);
         self.possibles.set((hint - 1) as usize, true);
     }
 
-    pub fn eliminate_possible(self: &mut Cell, digit: i32) -> Result<(), EliminationError> {
-        self.possibles.set((digit - 1) as usize, false);
-        if self.possibles.len() == 0 {
+    /// Returns whether `digit` was possible before.
+    pub fn eliminate_possible(self: &mut Cell, digit: i32) -> Result<bool, EliminationError> {
+        let was_possible = self.possibles.set((digit - 1) as usize, false);
+        if self.possibles.is_empty() {
             Err(EliminationError {})
         } else {
-            Ok(())
+            Ok(was_possible)
         }
     }
 
@@ -45,6 +64,15 @@ impl Cell {
         }
     }
 
+    /// The possible digits as a mask with bit `digit - 1` set for each.
+    pub fn possibles_mask(&self) -> u32 {
+        self.possibles.into_value()
+    }
+
+    pub fn possibles(&self) -> Vec<i32> {
+        self.possibles.into_iter().map(|i| (i + 1) as i32).collect()
+    }
+
     pub fn possibles_except(&self, except: i32) -> Vec<i32> {
         self.possibles
             .into_iter()
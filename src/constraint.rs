@@ -0,0 +1,776 @@
+//! Constraints of arithmetic variants that relate the digits of a few cells:
+//! thermometers, arrows, sandwich sums, Kropki dots, XV pairs and little
+//! killer diagonals.
+//!
+//! A [`Constraint`] narrows down the candidates of its cells and fails once
+//! it can no longer be met, so other kinds only need to implement it.
+//! Puzzles declare theirs in JSON, read with [`from_json`].
+
+use std::fmt;
+use std::rc::Rc;
+
+use serde_json::{json, Map, Value};
+
+use crate::cell::EliminationError;
+use crate::house::{cell_name, parse_cell_name, House, Shape};
+
+/// A rule between the digits of some cells, on top of the houses and peers.
+/// It displays as what it says about the cells, which explains the
+/// deductions it makes.
+pub trait Constraint: fmt::Debug + fmt::Display {
+    /// The cells the constraint holds between.
+    fn cells(&self) -> &[(i32, i32)];
+
+    /// The candidates of the cells that can still meet the constraint, given
+    /// their candidates in the order of `cells` as masks with bit
+    /// `digit - 1` set, in a grid of the digits 1 to `size`. Fails if the
+    /// constraint cannot be met. Digits may be kept that only the houses of
+    /// the cells rule out.
+    fn prune(&self, size: i32, possibles: &[u32]) -> Result<Vec<u32>, EliminationError>;
+
+    /// The JSON object declaring the constraint, which `from_json` reads
+    /// back.
+    fn to_json(&self) -> Value;
+}
+
+fn cell_names(cells: &[(i32, i32)]) -> Vec<String> {
+    cells.iter().map(|&(x, y)| cell_name(x, y)).collect()
+}
+
+fn cell_list(cells: &[(i32, i32)]) -> String {
+    cells
+        .iter()
+        .map(|&(x, y)| cell_name(x, y))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// The digits in a candidate mask.
+fn digits(mask: u32) -> impl Iterator<Item = i32> + Clone {
+    (1..=Shape::MAX_SIZE).filter(move |digit| mask & (1 << (digit - 1)) != 0)
+}
+
+fn mask(digits: impl Iterator<Item = i32>) -> u32 {
+    digits.fold(0, |mask, digit| mask | 1 << (digit - 1))
+}
+
+/// The largest sum `cells` cells of a grid of the digits 1 to `size` can
+/// add up to, which bounds the sums worth searching for.
+fn max_sum(size: i32, cells: usize) -> i32 {
+    size.saturating_mul(cells as i32)
+}
+
+/// Fails if a cell has no candidates left.
+fn checked(allowed: Vec<u32>) -> Result<Vec<u32>, EliminationError> {
+    if allowed.contains(&0) {
+        Err(EliminationError {})
+    } else {
+        Ok(allowed)
+    }
+}
+
+/// The candidates of cells whose digits, which may repeat, add up to one of
+/// the sums set in `totals`, and which of those sums they can reach.
+fn sum_possibles(possibles: &[u32], totals: &[bool]) -> (Vec<u32>, Vec<bool>) {
+    let limit = totals.len();
+    let cells: Vec<Vec<usize>> = possibles
+        .iter()
+        .map(|&possible| digits(possible).map(|digit| digit as usize).collect())
+        .collect();
+    // The sums the cells before each one can add up to.
+    let mut reached = vec![vec![false; limit]; cells.len() + 1];
+    reached[0][0] = true;
+    for (i, digits) in cells.iter().enumerate() {
+        for sum in 0..limit {
+            if reached[i][sum] {
+                for &digit in digits.iter().filter(|&&digit| sum + digit < limit) {
+                    reached[i + 1][sum + digit] = true;
+                }
+            }
+        }
+    }
+    // The sums from which the cells after each one can reach a total.
+    let mut needed = vec![totals.to_vec(); cells.len() + 1];
+    for (i, digits) in cells.iter().enumerate().rev() {
+        for sum in 0..limit {
+            needed[i][sum] = digits
+                .iter()
+                .any(|&digit| sum + digit < limit && needed[i + 1][sum + digit]);
+        }
+    }
+    let allowed = cells
+        .iter()
+        .enumerate()
+        .map(|(i, digits)| {
+            mask(digits.iter().map(|&digit| digit as i32).filter(|&digit| {
+                let digit = digit as usize;
+                (0..limit.saturating_sub(digit))
+                    .any(|sum| reached[i][sum] && needed[i + 1][sum + digit])
+            }))
+        })
+        .collect();
+    let reachable = totals
+        .iter()
+        .zip(&reached[possibles.len()])
+        .map(|(&total, &reached)| total && reached)
+        .collect();
+    (allowed, reachable)
+}
+
+/// The candidates of two cells whose digits are `related`.
+fn pair_possibles(
+    possibles: &[u32],
+    related: impl Fn(i32, i32) -> bool,
+) -> Result<Vec<u32>, EliminationError> {
+    let (first, second) = (possibles[0], possibles[1]);
+    checked(vec![
+        mask(digits(first).filter(|&a| digits(second).any(|b| related(a, b)))),
+        mask(digits(second).filter(|&b| digits(first).any(|a| related(a, b)))),
+    ])
+}
+
+/// Digits increase from the bulb of the thermometer to its tip.
+#[derive(Clone, Debug)]
+pub struct Thermometer {
+    cells: Vec<(i32, i32)>,
+}
+
+impl Thermometer {
+    /// A thermometer with the bulb in the first cell.
+    pub fn new(cells: &[(i32, i32)]) -> Thermometer {
+        Thermometer {
+            cells: cells.to_vec(),
+        }
+    }
+}
+
+impl Constraint for Thermometer {
+    fn cells(&self) -> &[(i32, i32)] {
+        &self.cells
+    }
+
+    fn prune(&self, _size: i32, possibles: &[u32]) -> Result<Vec<u32>, EliminationError> {
+        let mut allowed = possibles.to_vec();
+        for i in 1..allowed.len() {
+            if allowed[i - 1] == 0 {
+                return Err(EliminationError {});
+            }
+            // Above the lowest digit of the cell before, none above 32.
+            allowed[i] &= (!0u32)
+                .checked_shl(allowed[i - 1].trailing_zeros() + 1)
+                .unwrap_or(0);
+        }
+        for i in (1..allowed.len()).rev() {
+            if allowed[i] == 0 {
+                return Err(EliminationError {});
+            }
+            // Below the highest digit of the cell after.
+            allowed[i - 1] &= (1 << (31 - allowed[i].leading_zeros())) - 1;
+        }
+        checked(allowed)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({"type": "thermo", "cells": cell_names(&self.cells)})
+    }
+}
+
+impl fmt::Display for Thermometer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} increase along a thermometer", cell_list(&self.cells))
+    }
+}
+
+/// The digits along an arrow, which may repeat, add up to the digit in its
+/// circle.
+#[derive(Clone, Debug)]
+pub struct Arrow {
+    /// The circle followed by the arrow.
+    cells: Vec<(i32, i32)>,
+}
+
+impl Arrow {
+    pub fn new(circle: (i32, i32), arrow: &[(i32, i32)]) -> Arrow {
+        let mut cells = vec![circle];
+        cells.extend_from_slice(arrow);
+        Arrow { cells }
+    }
+}
+
+impl Constraint for Arrow {
+    /// The circle first, then the cells of the arrow.
+    fn cells(&self) -> &[(i32, i32)] {
+        &self.cells
+    }
+
+    fn prune(&self, size: i32, possibles: &[u32]) -> Result<Vec<u32>, EliminationError> {
+        let (&circle, arrow) = possibles.split_first().expect("An arrow has a circle");
+        let mut totals = vec![false; size as usize + 1];
+        for digit in digits(circle) {
+            totals[digit as usize] = true;
+        }
+        let (mut allowed, reached) = sum_possibles(arrow, &totals);
+        allowed.insert(0, mask(digits(circle).filter(|&d| reached[d as usize])));
+        checked(allowed)
+    }
+
+    fn to_json(&self) -> Value {
+        let (circle, arrow) = self.cells.split_first().expect("An arrow has a circle");
+        json!({
+            "type": "arrow",
+            "circle": cell_name(circle.0, circle.1),
+            "cells": cell_names(arrow),
+        })
+    }
+}
+
+impl fmt::Display for Arrow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (circle, arrow) = self.cells.split_first().expect("An arrow has a circle");
+        write!(
+            f,
+            "{} add up to {} along an arrow",
+            cell_list(arrow),
+            cell_name(circle.0, circle.1)
+        )
+    }
+}
+
+/// The digits of a row or column between its 1 and its highest digit add up
+/// to the sum.
+#[derive(Clone, Debug)]
+pub struct Sandwich {
+    house: House,
+    sum: i32,
+    cells: Vec<(i32, i32)>,
+}
+
+impl Sandwich {
+    /// A sandwich sum for a row or column of a grid of `shape`.
+    pub fn new(shape: Shape, house: House, sum: i32) -> Sandwich {
+        Sandwich {
+            house,
+            sum,
+            cells: house.cells(shape).collect(),
+        }
+    }
+}
+
+impl Constraint for Sandwich {
+    fn cells(&self) -> &[(i32, i32)] {
+        &self.cells
+    }
+
+    fn prune(&self, size: i32, possibles: &[u32]) -> Result<Vec<u32>, EliminationError> {
+        if self.sum > max_sum(size, possibles.len()) {
+            return Err(EliminationError {});
+        }
+        let (low, high) = (1, 1u32 << (size - 1));
+        let filling = (u32::MAX >> (32 - size)) & !(low | high);
+        let mut totals = vec![false; self.sum as usize + 1];
+        totals[self.sum as usize] = true;
+        let mut allowed = vec![0; possibles.len()];
+        for first in 0..possibles.len() {
+            for last in first + 1..possibles.len() {
+                let low_first = possibles[first] & low != 0 && possibles[last] & high != 0;
+                let high_first = possibles[first] & high != 0 && possibles[last] & low != 0;
+                // The digits between are different, so their number bounds
+                // the sum.
+                let between = (last - first - 1) as i32;
+                if !(low_first || high_first)
+                    || between > size - 2
+                    || self.sum < (2..2 + between).sum()
+                    || self.sum > (size - between..size).sum()
+                {
+                    continue;
+                }
+                let inside: Vec<u32> = possibles[first + 1..last]
+                    .iter()
+                    .map(|&possible| possible & filling)
+                    .collect();
+                let (inside, reached) = sum_possibles(&inside, &totals);
+                if !reached[self.sum as usize] {
+                    continue;
+                }
+                for (i, &possible) in possibles.iter().enumerate() {
+                    if i < first || i > last {
+                        allowed[i] |= possible & filling;
+                    }
+                }
+                for (i, &possible) in inside.iter().enumerate() {
+                    allowed[first + 1 + i] |= possible;
+                }
+                if low_first {
+                    allowed[first] |= low;
+                    allowed[last] |= high;
+                }
+                if high_first {
+                    allowed[first] |= high;
+                    allowed[last] |= low;
+                }
+            }
+        }
+        checked(allowed)
+    }
+
+    fn to_json(&self) -> Value {
+        match self.house {
+            House::Column(x) => json!({"type": "sandwich", "column": x + 1, "sum": self.sum}),
+            House::Row(y) => json!({"type": "sandwich", "row": y + 1, "sum": self.sum}),
+            _ => unreachable!("A sandwich is in a row or column"),
+        }
+    }
+}
+
+impl fmt::Display for Sandwich {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the digits between 1 and {} in {} add up to {}",
+            self.cells.len(),
+            self.house,
+            self.sum
+        )
+    }
+}
+
+/// The kinds of Kropki dots between two adjacent cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dot {
+    /// The digits are consecutive.
+    White,
+    /// One digit is double the other.
+    Black,
+}
+
+/// A Kropki dot between two adjacent cells.
+#[derive(Clone, Debug)]
+pub struct Kropki {
+    dot: Dot,
+    cells: [(i32, i32); 2],
+}
+
+impl Kropki {
+    pub fn new(dot: Dot, first: (i32, i32), second: (i32, i32)) -> Kropki {
+        Kropki {
+            dot,
+            cells: [first, second],
+        }
+    }
+}
+
+impl Constraint for Kropki {
+    fn cells(&self) -> &[(i32, i32)] {
+        &self.cells
+    }
+
+    fn prune(&self, _size: i32, possibles: &[u32]) -> Result<Vec<u32>, EliminationError> {
+        match self.dot {
+            Dot::White => pair_possibles(possibles, |a, b| (a - b).abs() == 1),
+            Dot::Black => pair_possibles(possibles, |a, b| a == 2 * b || b == 2 * a),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let color = match self.dot {
+            Dot::White => "white",
+            Dot::Black => "black",
+        };
+        json!({"type": "kropki", "color": color, "cells": cell_names(&self.cells)})
+    }
+}
+
+impl fmt::Display for Kropki {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [(first_x, first_y), (second_x, second_y)] = self.cells;
+        let (first, second) = (cell_name(first_x, first_y), cell_name(second_x, second_y));
+        match self.dot {
+            Dot::White => write!(f, "{} and {} are consecutive", first, second),
+            Dot::Black => write!(f, "one of {} and {} is double the other", first, second),
+        }
+    }
+}
+
+/// The digits of two adjacent cells add up to 10 for an X or 5 for a V.
+#[derive(Clone, Debug)]
+pub struct Xv {
+    sum: i32,
+    cells: [(i32, i32); 2],
+}
+
+impl Xv {
+    pub fn new(sum: i32, first: (i32, i32), second: (i32, i32)) -> Xv {
+        Xv {
+            sum,
+            cells: [first, second],
+        }
+    }
+}
+
+impl Constraint for Xv {
+    fn cells(&self) -> &[(i32, i32)] {
+        &self.cells
+    }
+
+    fn prune(&self, _size: i32, possibles: &[u32]) -> Result<Vec<u32>, EliminationError> {
+        pair_possibles(possibles, |a, b| a + b == self.sum)
+    }
+
+    fn to_json(&self) -> Value {
+        let kind = if self.sum == 5 { "v" } else { "x" };
+        json!({"type": kind, "cells": cell_names(&self.cells)})
+    }
+}
+
+impl fmt::Display for Xv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} add up to {}", cell_list(&self.cells), self.sum)
+    }
+}
+
+/// The digits along a diagonal, which may repeat, add up to the sum given
+/// outside the grid.
+#[derive(Clone, Debug)]
+pub struct LittleKiller {
+    sum: i32,
+    cells: Vec<(i32, i32)>,
+}
+
+impl LittleKiller {
+    pub fn new(sum: i32, cells: &[(i32, i32)]) -> LittleKiller {
+        LittleKiller {
+            sum,
+            cells: cells.to_vec(),
+        }
+    }
+}
+
+impl Constraint for LittleKiller {
+    fn cells(&self) -> &[(i32, i32)] {
+        &self.cells
+    }
+
+    fn prune(&self, size: i32, possibles: &[u32]) -> Result<Vec<u32>, EliminationError> {
+        if self.sum > max_sum(size, possibles.len()) {
+            return Err(EliminationError {});
+        }
+        let mut totals = vec![false; self.sum as usize + 1];
+        totals[self.sum as usize] = true;
+        let (allowed, _) = sum_possibles(possibles, &totals);
+        checked(allowed)
+    }
+
+    /// Starts from the first cell, in the direction of the second. A single
+    /// cell is a corner, left in the direction that leaves the grid at once.
+    fn to_json(&self) -> Value {
+        let (x, y) = self.cells[0];
+        let (dx, dy) = match self.cells.get(1) {
+            Some(&(next_x, next_y)) => (next_x - x, next_y - y),
+            None => (if x == 0 { -1 } else { 1 }, if y == 0 { -1 } else { 1 }),
+        };
+        let direction = match (dx, dy) {
+            (1, 1) => "down-right",
+            (-1, 1) => "down-left",
+            (1, -1) => "up-right",
+            _ => "up-left",
+        };
+        json!({
+            "type": "little-killer",
+            "sum": self.sum,
+            "start": cell_name(x, y),
+            "direction": direction,
+        })
+    }
+}
+
+impl fmt::Display for LittleKiller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} add up to {} along a diagonal",
+            cell_list(&self.cells),
+            self.sum
+        )
+    }
+}
+
+/// The types of the constraints `from_json` reads.
+pub const TYPES: [&str; 7] = [
+    "thermo",
+    "arrow",
+    "sandwich",
+    "kropki",
+    "x",
+    "v",
+    "little-killer",
+];
+
+/// Reads the constraints declared by a JSON object, or an array of them,
+/// for a grid of `shape`. Cells are named like `r1c1` and the `type` of
+/// each object tells what it declares:
+///
+/// - `{"type": "thermo", "cells": ["r1c1", "r1c2", "r1c3"]}`, from the bulb
+/// - `{"type": "arrow", "circle": "r1c1", "cells": ["r1c2", "r2c3"]}`
+/// - `{"type": "sandwich", "row": 1, "sum": 15}`, or a `column`
+/// - `{"type": "kropki", "color": "white", "cells": ["r1c1", "r1c2"]}`, or
+///   `black`
+/// - `{"type": "x", "cells": ["r1c1", "r1c2"]}`, or `v`
+/// - `{"type": "little-killer", "sum": 23, "start": "r1c2", "direction":
+///   "down-right"}`, for the diagonal from `start` to the edge of the grid
+pub fn from_json(value: &Value, shape: Shape) -> Result<Vec<Rc<dyn Constraint>>, String> {
+    let objects: Vec<&Value> = match value {
+        Value::Array(objects) => objects.iter().collect(),
+        _ => vec![value],
+    };
+    let numbered = objects.len() > 1;
+    objects
+        .into_iter()
+        .enumerate()
+        .map(|(i, object)| {
+            parse_constraint(object, shape).map_err(|message| {
+                if numbered {
+                    format!("constraint {}: {}", i + 1, message)
+                } else {
+                    message
+                }
+            })
+        })
+        .collect()
+}
+
+fn parse_constraint(value: &Value, shape: Shape) -> Result<Rc<dyn Constraint>, String> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| format!("expected an object, found {}", value))?;
+    let kind = field(object, "type")?;
+    let constraint: Rc<dyn Constraint> = match kind.as_str().unwrap_or_default() {
+        "thermo" => {
+            let cells = parse_cells(field(object, "cells")?, shape)?;
+            if cells.len() < 2 {
+                return Err("a thermometer needs at least two cells".to_string());
+            }
+            Rc::new(Thermometer::new(&cells))
+        }
+        "arrow" => {
+            let circle = parse_cell(field(object, "circle")?, shape)?;
+            let arrow = parse_cells(field(object, "cells")?, shape)?;
+            if arrow.is_empty() {
+                return Err("an arrow needs at least one cell".to_string());
+            }
+            if arrow.contains(&circle) {
+                return Err(format!(
+                    "{} is both the circle and on the arrow",
+                    cell_name(circle.0, circle.1)
+                ));
+            }
+            Rc::new(Arrow::new(circle, &arrow))
+        }
+        "sandwich" => {
+            let sum = parse_sum(field(object, "sum")?, shape, shape.size() as usize)?;
+            let house = match (object.get("row"), object.get("column")) {
+                (Some(row), None) => House::Row(parse_line(row, shape)?),
+                (None, Some(column)) => House::Column(parse_line(column, shape)?),
+                _ => return Err("expected either \"row\" or \"column\"".to_string()),
+            };
+            Rc::new(Sandwich::new(shape, house, sum))
+        }
+        "kropki" => {
+            let dot = match field(object, "color")?.as_str() {
+                Some("white") => Dot::White,
+                Some("black") => Dot::Black,
+                _ => return Err("expected a \"color\" of white or black".to_string()),
+            };
+            let [first, second] = parse_pair(field(object, "cells")?, shape)?;
+            Rc::new(Kropki::new(dot, first, second))
+        }
+        letter @ ("x" | "v") => {
+            let sum = if letter == "x" { 10 } else { 5 };
+            let [first, second] = parse_pair(field(object, "cells")?, shape)?;
+            Rc::new(Xv::new(sum, first, second))
+        }
+        "little-killer" => {
+            let sum = field(object, "sum")?;
+            let (mut x, mut y) = parse_cell(field(object, "start")?, shape)?;
+            let (dx, dy) =
+                match field(object, "direction")?.as_str() {
+                    Some("down-right") => (1, 1),
+                    Some("down-left") => (-1, 1),
+                    Some("up-right") => (1, -1),
+                    Some("up-left") => (-1, -1),
+                    _ => return Err(
+                        "expected a \"direction\" of down-right, down-left, up-right or up-left"
+                            .to_string(),
+                    ),
+                };
+            let inside = |v: i32| (0..shape.size()).contains(&v);
+            let mut cells = Vec::new();
+            while inside(x) && inside(y) {
+                cells.push((x, y));
+                x += dx;
+                y += dy;
+            }
+            let sum = parse_sum(sum, shape, cells.len())?;
+            Rc::new(LittleKiller::new(sum, &cells))
+        }
+        _ => {
+            return Err(format!(
+                "unknown constraint type {}, expected one of {}",
+                kind,
+                TYPES.join(", ")
+            ))
+        }
+    };
+    Ok(constraint)
+}
+
+fn field<'a>(object: &'a Map<String, Value>, name: &str) -> Result<&'a Value, String> {
+    object
+        .get(name)
+        .ok_or_else(|| format!("missing \"{}\"", name))
+}
+
+/// The sum of `cells` cells, which they must be able to reach.
+fn parse_sum(value: &Value, shape: Shape, cells: usize) -> Result<i32, String> {
+    let sum = value
+        .as_u64()
+        .ok_or_else(|| format!("expected a sum, found {}", value))?;
+    let max = max_sum(shape.size(), cells);
+    if sum > max as u64 {
+        return Err(format!(
+            "a sum of {} is more than {} cells can add up to, at most {}",
+            sum, cells, max
+        ));
+    }
+    Ok(sum as i32)
+}
+
+/// A row or column numbered from 1.
+fn parse_line(value: &Value, shape: Shape) -> Result<i32, String> {
+    match value.as_i64() {
+        Some(n) if (1..=shape.size() as i64).contains(&n) => Ok(n as i32 - 1),
+        _ => Err(format!(
+            "expected a row or column from 1 to {}, found {}",
+            shape.size(),
+            value
+        )),
+    }
+}
+
+fn parse_cell(value: &Value, shape: Shape) -> Result<(i32, i32), String> {
+    let name = value
+        .as_str()
+        .ok_or_else(|| format!("expected a cell like \"r1c1\", found {}", value))?;
+    let (x, y) = parse_cell_name(name)
+        .ok_or_else(|| format!("invalid cell '{}', expected a name like r1c1", name))?;
+    if x >= shape.size() || y >= shape.size() {
+        return Err(format!("{} is outside the {} grid", cell_name(x, y), shape));
+    }
+    Ok((x, y))
+}
+
+fn parse_cells(value: &Value, shape: Shape) -> Result<Vec<(i32, i32)>, String> {
+    let values = value
+        .as_array()
+        .ok_or_else(|| format!("expected a list of cells, found {}", value))?;
+    let mut cells = Vec::new();
+    for value in values {
+        let cell = parse_cell(value, shape)?;
+        if cells.contains(&cell) {
+            return Err(format!("{} appears twice", cell_name(cell.0, cell.1)));
+        }
+        cells.push(cell);
+    }
+    Ok(cells)
+}
+
+/// Two orthogonally adjacent cells.
+fn parse_pair(value: &Value, shape: Shape) -> Result<[(i32, i32); 2], String> {
+    match parse_cells(value, shape)?[..] {
+        [first, second] if (first.0 - second.0).abs() + (first.1 - second.1).abs() == 1 => {
+            Ok([first, second])
+        }
+        _ => Err(format!("expected two adjacent cells, found {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all(size: i32, cells: usize) -> Vec<u32> {
+        vec![u32::MAX >> (32 - size); cells]
+    }
+
+    #[test]
+    fn thermometer_prunes_the_largest_grid() {
+        let thermo = Thermometer::new(&[(0, 0), (1, 0)]);
+        let allowed = thermo.prune(32, &all(32, 2)).unwrap();
+        assert_eq!(allowed, vec![u32::MAX >> 1, u32::MAX << 1]);
+        assert!(thermo.prune(32, &[1 << 31, u32::MAX]).is_err());
+    }
+
+    #[test]
+    fn sandwich_prunes_the_largest_grid() {
+        let shape = Shape::new(8, 4);
+        // All the digits from 2 to 31 lie between the 1 and the 32.
+        let sandwich = Sandwich::new(shape, House::Row(0), (2..32).sum());
+        let allowed = sandwich.prune(32, &all(32, 32)).unwrap();
+        assert_eq!(allowed[0], 1 | 1 << 31);
+        assert_eq!(allowed[1], !(1 | 1 << 31));
+    }
+
+    #[test]
+    fn sums_out_of_reach_are_rejected() {
+        let shape = Shape::new(3, 3);
+        let sandwich = json!({"type": "sandwich", "row": 1, "sum": 82});
+        assert!(from_json(&sandwich, shape).is_err());
+        let killer = json!({
+            "type": "little-killer",
+            "sum": i32::MAX,
+            "start": "r1c2",
+            "direction": "down-right",
+        });
+        let err = from_json(&killer, shape).err().unwrap();
+        assert!(err.contains("more than 8 cells"), "{}", err);
+        let cells: Vec<(i32, i32)> = (0..3).map(|i| (i, i)).collect();
+        assert!(LittleKiller::new(i32::MAX, &cells)
+            .prune(9, &all(9, 3))
+            .is_err());
+        assert!(Sandwich::new(shape, House::Column(0), i32::MAX)
+            .prune(9, &all(9, 9))
+            .is_err());
+    }
+
+    #[test]
+    fn little_killer_needs_its_sum() {
+        let cells = [(0, 0), (1, 1)];
+        let allowed = LittleKiller::new(3, &cells).prune(9, &all(9, 2)).unwrap();
+        assert_eq!(allowed, vec![0b11, 0b11]);
+        assert!(LittleKiller::new(19, &cells).prune(9, &all(9, 2)).is_err());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let shape = Shape::new(3, 3);
+        let declared = json!([
+            {"type": "thermo", "cells": ["r1c1", "r1c2", "r2c2"]},
+            {"type": "arrow", "circle": "r3c3", "cells": ["r3c4", "r4c5"]},
+            {"type": "sandwich", "row": 4, "sum": 15},
+            {"type": "sandwich", "column": 9, "sum": 0},
+            {"type": "kropki", "color": "black", "cells": ["r5c5", "r5c6"]},
+            {"type": "kropki", "color": "white", "cells": ["r6c5", "r6c6"]},
+            {"type": "x", "cells": ["r7c1", "r8c1"]},
+            {"type": "v", "cells": ["r7c2", "r8c2"]},
+            {"type": "little-killer", "sum": 20, "start": "r9c2", "direction": "up-right"},
+            {"type": "little-killer", "sum": 9, "start": "r9c9", "direction": "down-right"},
+        ]);
+        let constraints = from_json(&declared, shape).unwrap();
+        let written = Value::Array(constraints.iter().map(|c| c.to_json()).collect());
+        let read = from_json(&written, shape).unwrap();
+        assert_eq!(constraints.len(), read.len());
+        for (constraint, again) in constraints.iter().zip(&read) {
+            assert_eq!(constraint.cells(), again.cells());
+            assert_eq!(constraint.to_string(), again.to_string());
+        }
+    }
+}
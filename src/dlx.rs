@@ -0,0 +1,641 @@
+//! An exact cover backend using Knuth's Algorithm X with dancing links.
+//!
+//! Every candidate, a digit in a cell, is a row of a sparse 0/1 matrix with
+//! a 1 in the column of its cell and in the column of the digit in each of
+//! its houses. A solution picks rows covering every one of those primary
+//! columns exactly once. Peers outside the houses, like anti-knight cells or
+//! cells of a cage, and non-consecutive neighbours are secondary columns,
+//! which may be covered at most once, for every pair of candidates that
+//! cannot both be picked. Cage sums and constraints have no such encoding,
+//! instead they hide the rows they rule out after every pick.
+//!
+//! The search undoes its picks by relinking nodes instead of cloning the
+//! grid, and draws nothing while it runs.
+
+use std::rc::Rc;
+
+use crate::multigrid::Layout;
+use crate::random::Random;
+use crate::rules::Rules;
+use crate::solver::{SolveOutcome, SolveReport, SolveStats, Solver};
+
+/// A sparse matrix of linked nodes. Node 0 is the root, followed by the
+/// header of every column and then the nodes of the rows.
+struct Matrix {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    /// The header of every node.
+    column: Vec<usize>,
+    /// The row of every node, unused for the root and headers.
+    row: Vec<usize>,
+    /// The rows left in every column, indexed by header.
+    size: Vec<usize>,
+}
+
+impl Matrix {
+    /// A matrix without rows. Only the `primary` columns are linked to the
+    /// root, the secondary ones after them are never chosen to be covered.
+    fn new(primary: usize, secondary: usize) -> Matrix {
+        let headers = 1 + primary + secondary;
+        let mut matrix = Matrix {
+            left: (0..headers).collect(),
+            right: (0..headers).collect(),
+            up: (0..headers).collect(),
+            down: (0..headers).collect(),
+            column: (0..headers).collect(),
+            row: vec![usize::MAX; headers],
+            size: vec![0; headers],
+        };
+        for header in 0..=primary {
+            matrix.left[header] = if header == 0 { primary } else { header - 1 };
+            matrix.right[header] = if header == primary { 0 } else { header + 1 };
+        }
+        matrix
+    }
+
+    /// The header of column `column`, counted from 0.
+    fn header(column: usize) -> usize {
+        column + 1
+    }
+
+    /// Adds row `row` with a 1 in each of `columns`.
+    fn add_row(&mut self, row: usize, columns: &[usize]) {
+        let first = self.left.len();
+        let last = first + columns.len() - 1;
+        for (node, &column) in (first..).zip(columns) {
+            let header = Matrix::header(column);
+            self.left.push(if node == first { last } else { node - 1 });
+            self.right.push(if node == last { first } else { node + 1 });
+            self.up.push(self.up[header]);
+            self.down.push(header);
+            let above = self.up[header];
+            self.down[above] = node;
+            self.up[header] = node;
+            self.column.push(header);
+            self.row.push(row);
+            self.size[header] += 1;
+        }
+    }
+
+    /// Unlinks a column and every row with a 1 in it from the other columns.
+    fn cover(&mut self, header: usize) {
+        let (left, right) = (self.left[header], self.right[header]);
+        self.right[left] = right;
+        self.left[right] = left;
+        let mut i = self.down[header];
+        while i != header {
+            let mut j = self.right[i];
+            while j != i {
+                let (up, down) = (self.up[j], self.down[j]);
+                self.down[up] = down;
+                self.up[down] = up;
+                self.size[self.column[j]] -= 1;
+                j = self.right[j];
+            }
+            i = self.down[i];
+        }
+    }
+
+    /// Relinks what `cover` unlinked, which must be done in reverse order.
+    fn uncover(&mut self, header: usize) {
+        let mut i = self.up[header];
+        while i != header {
+            let mut j = self.left[i];
+            while j != i {
+                self.size[self.column[j]] += 1;
+                let (up, down) = (self.up[j], self.down[j]);
+                self.down[up] = j;
+                self.up[down] = j;
+                j = self.left[j];
+            }
+            i = self.up[i];
+        }
+        let (left, right) = (self.left[header], self.right[header]);
+        self.right[left] = header;
+        self.left[right] = header;
+    }
+
+    /// Unlinks the row of `node` from its columns, leaving the rest alone.
+    fn hide(&mut self, node: usize) {
+        let mut j = node;
+        loop {
+            let (up, down) = (self.up[j], self.down[j]);
+            self.down[up] = down;
+            self.up[down] = up;
+            self.size[self.column[j]] -= 1;
+            j = self.right[j];
+            if j == node {
+                break;
+            }
+        }
+    }
+
+    /// Relinks what `hide` unlinked, which must be done in reverse order.
+    fn unhide(&mut self, node: usize) {
+        let mut j = node;
+        loop {
+            j = self.left[j];
+            self.size[self.column[j]] += 1;
+            let (up, down) = (self.up[j], self.down[j]);
+            self.down[up] = j;
+            self.up[down] = j;
+            if j == node {
+                break;
+            }
+        }
+    }
+
+    /// The primary column with the fewest rows left, `None` once all of them
+    /// are covered.
+    fn fewest_rows(&self) -> Option<usize> {
+        let mut best = None;
+        let mut header = self.right[0];
+        while header != 0 {
+            if best.is_none_or(|best| self.size[header] < self.size[best]) {
+                best = Some(header);
+                if self.size[header] <= 1 {
+                    break;
+                }
+            }
+            header = self.right[header];
+        }
+        best
+    }
+}
+
+/// A cage or constraint pruning the candidates after every pick, with the
+/// cells involved in order.
+enum Check {
+    Cage(usize, Vec<usize>),
+    Constraint(usize, Vec<usize>),
+}
+
+/// Why the search stopped before trying every row.
+enum Stop {
+    /// The solution callback asked to stop.
+    Stopped,
+    /// The guess limit was reached.
+    Aborted,
+}
+
+/// Solves puzzles as an exact cover problem, see the module documentation.
+pub struct DlxSolver {
+    rules: Rc<Rules>,
+    /// Digits in each cell.
+    size: i32,
+    /// Cells in row-major order, numbered like their columns.
+    num_cells: usize,
+    /// The number of every cell of the canvas, `None` where there is none.
+    indices: Vec<Option<usize>>,
+    width: i32,
+    /// The houses of every cell, numbered across all grids.
+    houses_of: Vec<Vec<usize>>,
+    num_houses: usize,
+    /// Pairs of candidates, as cell and digit, that cannot both be picked.
+    conflicts: Vec<[(usize, i32); 2]>,
+    checks: Vec<Check>,
+    hints: Vec<Option<i32>>,
+    check_unique: bool,
+    guess_limit: Option<usize>,
+    seed: Option<u64>,
+    stats: SolveStats,
+    /// The digit picked for every cell so far, 0 for none.
+    solution: Vec<i32>,
+}
+
+impl Solver for DlxSolver {
+    fn set_hint(&mut self, x: i32, y: i32, hint: i32) {
+        let cell = self.index(x, y);
+        self.hints[cell] = Some(hint);
+    }
+
+    fn solve(&mut self) -> SolveReport {
+        let limit = if self.check_unique { 2 } else { 1 };
+        let mut solutions = Vec::new();
+        let end = self.search(|found| {
+            solutions.push(found);
+            solutions.len() < limit
+        });
+        let aborted = matches!(end, Err(Stop::Aborted));
+        let outcome = match (aborted, solutions.len()) {
+            (_, 2) => {
+                let second = solutions.pop().unwrap();
+                SolveOutcome::MultipleSolutions(solutions.pop().unwrap(), second)
+            }
+            (true, _) => SolveOutcome::Aborted,
+            (_, 1) => SolveOutcome::Solved(solutions.pop().unwrap()),
+            _ => SolveOutcome::Unsolvable,
+        };
+        SolveReport {
+            outcome,
+            stats: self.stats.clone(),
+        }
+    }
+
+    fn count_solutions(&mut self, limit: usize) -> usize {
+        let mut count = 0;
+        if limit > 0 {
+            let _ = self.search(|_| {
+                count += 1;
+                count < limit
+            });
+        }
+        count
+    }
+}
+
+impl DlxSolver {
+    /// A solver for a single grid following `rules`.
+    pub fn new(rules: Rc<Rules>) -> DlxSolver {
+        let shape = rules.shape();
+        let indices = (0..shape.num_cells()).map(Some).collect();
+        DlxSolver::with_cells(rules, shape.num_cells(), indices, shape.size(), &[(0, 0)])
+    }
+
+    /// A solver for the grids of `layout`, every one of them following
+    /// `rules`. Houses shared by two grids are only counted once.
+    pub fn with_layout(layout: &Layout, rules: Rc<Rules>) -> DlxSolver {
+        let mut indices = Vec::new();
+        for y in 0..layout.height() {
+            for x in 0..layout.width() {
+                if layout.contains(x, y) {
+                    indices.push(Some(layout.index(x, y)));
+                } else {
+                    indices.push(None);
+                }
+            }
+        }
+        let num_cells = layout.num_cells();
+        DlxSolver::with_cells(rules, num_cells, indices, layout.width(), layout.origins())
+    }
+
+    fn with_cells(
+        rules: Rc<Rules>,
+        num_cells: usize,
+        indices: Vec<Option<usize>>,
+        width: i32,
+        origins: &[(i32, i32)],
+    ) -> DlxSolver {
+        let index =
+            |(x, y): (i32, i32)| indices[(y * width + x) as usize].expect("Cell is covered");
+        let mut houses: Vec<Vec<usize>> = Vec::new();
+        let mut conflicts = Vec::new();
+        let mut checks = Vec::new();
+        for &(ox, oy) in origins {
+            let on_canvas = |cells: &[(i32, i32)]| -> Vec<usize> {
+                cells
+                    .iter()
+                    .map(|&(x, y)| index((ox + x, oy + y)))
+                    .collect()
+            };
+            for &house in rules.houses() {
+                let mut cells = on_canvas(rules.house_cells(house));
+                cells.sort_unstable();
+                if !houses.contains(&cells) {
+                    houses.push(cells);
+                }
+            }
+            let shape = rules.shape();
+            for i in 0..shape.num_cells() {
+                let (x, y) = shape.cell(i);
+                let cell = index((ox + x, oy + y));
+                for peer in on_canvas(rules.extra_peers(x, y)) {
+                    if cell < peer {
+                        for digit in 1..=shape.size() {
+                            conflicts.push([(cell, digit), (peer, digit)]);
+                        }
+                    }
+                }
+                for next in on_canvas(rules.neighbours(x, y)) {
+                    if cell < next {
+                        for digit in 1..shape.size() {
+                            conflicts.push([(cell, digit), (next, digit + 1)]);
+                            conflicts.push([(cell, digit + 1), (next, digit)]);
+                        }
+                    }
+                }
+            }
+            for (i, cage) in rules.cages().iter().enumerate() {
+                checks.push(Check::Cage(i, on_canvas(&cage.cells)));
+            }
+            for (i, constraint) in rules.constraints().iter().enumerate() {
+                checks.push(Check::Constraint(i, on_canvas(constraint.cells())));
+            }
+        }
+        // Pairs seen from both grids of a shared cell are only kept once.
+        conflicts.sort_unstable();
+        conflicts.dedup();
+        let mut houses_of = vec![Vec::new(); num_cells];
+        for (h, house) in houses.iter().enumerate() {
+            for &cell in house {
+                houses_of[cell].push(h);
+            }
+        }
+        DlxSolver {
+            size: rules.shape().size(),
+            rules,
+            num_cells,
+            indices,
+            width,
+            houses_of,
+            num_houses: houses.len(),
+            conflicts,
+            checks,
+            hints: vec![None; num_cells],
+            check_unique: false,
+            guess_limit: None,
+            seed: None,
+            stats: SolveStats::default(),
+            solution: vec![0; num_cells],
+        }
+    }
+
+    /// Keep searching after the first solution so that `solve` can report
+    /// puzzles with multiple solutions.
+    pub fn check_unique(mut self, check_unique: bool) -> Self {
+        self.check_unique = check_unique;
+        self
+    }
+
+    /// Give up with `SolveOutcome::Aborted` after this many guesses.
+    pub fn guess_limit(mut self, guess_limit: Option<usize>) -> Self {
+        self.guess_limit = guess_limit;
+        self
+    }
+
+    /// Try the candidates of a cell in a random order given by the seed,
+    /// instead of the lowest digit first.
+    pub fn seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        self.indices[(y * self.width + x) as usize].expect("Cell is covered")
+    }
+
+    /// The matrix of the candidates left by the hints, with the cell and
+    /// digit of every row.
+    fn build(&self) -> (Matrix, Vec<(usize, i32)>) {
+        let num_cells = self.num_cells;
+        let size = self.size as usize;
+        let primary = num_cells + self.num_houses * size;
+        let mut conflicts_of = vec![Vec::new(); num_cells * size];
+        for (k, pair) in self.conflicts.iter().enumerate() {
+            for &(cell, digit) in pair {
+                conflicts_of[cell * size + digit as usize - 1].push(primary + k);
+            }
+        }
+        let mut rows: Vec<(usize, i32)> = (0..num_cells)
+            .flat_map(|cell| (1..=self.size).map(move |digit| (cell, digit)))
+            .filter(|&(cell, digit)| self.hints[cell].is_none_or(|hint| hint == digit))
+            .collect();
+        if let Some(seed) = self.seed {
+            Random::new(seed).shuffle(&mut rows);
+        }
+        let mut matrix = Matrix::new(primary, self.conflicts.len());
+        let mut columns = Vec::new();
+        for (row, &(cell, digit)) in rows.iter().enumerate() {
+            columns.clear();
+            columns.push(cell);
+            for &house in &self.houses_of[cell] {
+                columns.push(num_cells + house * size + digit as usize - 1);
+            }
+            columns.extend(&conflicts_of[cell * size + digit as usize - 1]);
+            matrix.add_row(row, &columns);
+        }
+        (matrix, rows)
+    }
+
+    /// Runs Algorithm X, calling `on_solution` for every solution found until
+    /// it returns false.
+    fn search<F: FnMut(Vec<i32>) -> bool>(&mut self, mut on_solution: F) -> Result<(), Stop> {
+        self.stats = SolveStats::default();
+        let (mut matrix, rows) = self.build();
+        if !self.propagate(&mut matrix, &rows, &mut Vec::new()) {
+            return Ok(());
+        }
+        self.search_from(&mut matrix, &rows, 0, &mut on_solution)
+    }
+
+    fn search_from(
+        &mut self,
+        matrix: &mut Matrix,
+        rows: &[(usize, i32)],
+        depth: usize,
+        on_solution: &mut dyn FnMut(Vec<i32>) -> bool,
+    ) -> Result<(), Stop> {
+        let header = match matrix.fewest_rows() {
+            Some(header) => header,
+            None if on_solution(self.solution.clone()) => return Ok(()),
+            None => return Err(Stop::Stopped),
+        };
+        // A cell or a digit of a house has no candidate left.
+        if matrix.size[header] == 0 {
+            return Ok(());
+        }
+        let guessing = matrix.size[header] > 1;
+        let depth = if guessing { depth + 1 } else { depth };
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+        matrix.cover(header);
+        let mut result = Ok(());
+        let mut node = matrix.down[header];
+        while node != header {
+            if guessing {
+                if self
+                    .guess_limit
+                    .is_some_and(|limit| self.stats.guesses >= limit)
+                {
+                    result = Err(Stop::Aborted);
+                    break;
+                }
+                self.stats.guesses += 1;
+            }
+            let (cell, digit) = rows[matrix.row[node]];
+            self.solution[cell] = digit;
+            let mut j = matrix.right[node];
+            while j != node {
+                matrix.cover(matrix.column[j]);
+                j = matrix.right[j];
+            }
+            let mut hidden = Vec::new();
+            if self.propagate(matrix, rows, &mut hidden) {
+                result = self.search_from(matrix, rows, depth, on_solution);
+            }
+            for &node in hidden.iter().rev() {
+                matrix.unhide(node);
+            }
+            let mut j = matrix.left[node];
+            while j != node {
+                matrix.uncover(matrix.column[j]);
+                j = matrix.left[j];
+            }
+            self.solution[cell] = 0;
+            if result.is_err() {
+                break;
+            }
+            if guessing {
+                self.stats.backtracks += 1;
+            }
+            node = matrix.down[node];
+        }
+        matrix.uncover(header);
+        result
+    }
+
+    /// The digits `cell` may still hold as a mask, bit 0 for digit 1.
+    fn possibles(&self, matrix: &Matrix, rows: &[(usize, i32)], cell: usize) -> u32 {
+        if self.solution[cell] != 0 {
+            return 1 << (self.solution[cell] - 1);
+        }
+        let header = Matrix::header(cell);
+        let mut mask = 0;
+        let mut node = matrix.down[header];
+        while node != header {
+            mask |= 1 << (rows[matrix.row[node]].1 - 1);
+            node = matrix.down[node];
+        }
+        mask
+    }
+
+    /// Removes the rows the cages and constraints rule out until none are
+    /// left to remove, recording them in `hidden`. Returns false if a cage
+    /// or constraint cannot be satisfied any more.
+    fn propagate(
+        &self,
+        matrix: &mut Matrix,
+        rows: &[(usize, i32)],
+        hidden: &mut Vec<usize>,
+    ) -> bool {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for check in &self.checks {
+                let (Check::Cage(_, cells) | Check::Constraint(_, cells)) = check;
+                let possibles: Vec<u32> = cells
+                    .iter()
+                    .map(|&cell| self.possibles(matrix, rows, cell))
+                    .collect();
+                let allowed = match check {
+                    Check::Cage(cage, _) => self.rules.cage_possibles(*cage, &possibles),
+                    Check::Constraint(constraint, _) => {
+                        match self.rules.constraints()[*constraint].prune(self.size, &possibles) {
+                            Ok(allowed) => allowed,
+                            Err(_) => return false,
+                        }
+                    }
+                };
+                for ((&cell, &possible), &allowed) in cells.iter().zip(&possibles).zip(&allowed) {
+                    if allowed == 0 {
+                        return false;
+                    }
+                    if possible & !allowed == 0 {
+                        continue;
+                    }
+                    let header = Matrix::header(cell);
+                    let mut node = matrix.down[header];
+                    while node != header {
+                        let next = matrix.down[node];
+                        if allowed & 1 << (rows[matrix.row[node]].1 - 1) == 0 {
+                            matrix.hide(node);
+                            hidden.push(node);
+                        }
+                        node = next;
+                    }
+                    changed = true;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multigrid::MultiGrid;
+    use crate::observer::{DummyGridObserver, DummySolverObserver};
+    use crate::reader::{self, Format, Puzzle};
+    use crate::solver::{ObserveableGrid, SudokuSolver};
+
+    fn puzzles(input: &str, count: usize) -> Vec<Puzzle> {
+        reader::read(input.as_bytes(), Format::Auto)
+            .take(count)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn elimination(puzzle: &Puzzle) -> Box<dyn Solver> {
+        let rules = Rc::new(puzzle.rules());
+        let mut solver: Box<dyn Solver> = match &puzzle.layout {
+            Some(layout) => {
+                let grid = MultiGrid::new(DummyGridObserver {}, Rc::new(layout.clone()), rules);
+                Box::new(SudokuSolver::new(grid, DummySolverObserver {}).check_unique(true))
+            }
+            None => {
+                let grid = ObserveableGrid::with_rules(DummyGridObserver {}, rules);
+                Box::new(SudokuSolver::new(grid, DummySolverObserver {}).check_unique(true))
+            }
+        };
+        puzzle.apply(&mut *solver);
+        solver
+    }
+
+    fn dlx(puzzle: &Puzzle) -> Box<dyn Solver> {
+        let rules = Rc::new(puzzle.rules());
+        let solver = match &puzzle.layout {
+            Some(layout) => DlxSolver::with_layout(layout, rules),
+            None => DlxSolver::new(rules),
+        };
+        let mut solver: Box<dyn Solver> = Box::new(solver.check_unique(true));
+        puzzle.apply(&mut *solver);
+        solver
+    }
+
+    fn assert_agree(puzzles: &[Puzzle]) {
+        for (i, puzzle) in puzzles.iter().enumerate() {
+            let expected = elimination(puzzle).solve().outcome;
+            let found = dlx(puzzle).solve().outcome;
+            match expected {
+                SolveOutcome::MultipleSolutions(_, _) => {
+                    assert!(
+                        matches!(found, SolveOutcome::MultipleSolutions(_, _)),
+                        "case {}",
+                        i
+                    )
+                }
+                _ => assert_eq!(found, expected, "case {}", i),
+            }
+            assert_eq!(
+                dlx(puzzle).count_solutions(2),
+                elimination(puzzle).count_solutions(2),
+                "case {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn agrees_on_classic_puzzles() {
+        assert_agree(&puzzles(include_str!("../testdata/easy"), 20));
+        assert_agree(&puzzles(include_str!("../testdata/hard"), 5));
+    }
+
+    /// Killer, jigsaw, constraint, diagonal, small and samurai puzzles.
+    #[test]
+    fn agrees_on_variants() {
+        assert_agree(&puzzles(include_str!("../testdata/variants"), usize::MAX));
+    }
+
+    #[test]
+    fn agrees_without_a_single_solution() {
+        let input = "\
+            ................\n\
+            11..............\n\
+            variant: anti-king\n\
+            ................\n";
+        assert_agree(&puzzles(input, usize::MAX));
+    }
+}
@@ -0,0 +1,196 @@
+//! Generates puzzles with a single solution.
+//!
+//! A random full grid is found by solving the empty grid with random
+//! guesses. Clues are then removed in random order as long as the puzzle
+//! keeps a single solution.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::house::Shape;
+use crate::observer::{DummyGridObserver, DummySolverObserver};
+use crate::random::Random;
+use crate::rating::{self, Bucket};
+use crate::reader::Puzzle;
+use crate::solver::{ObserveableGrid, Solver, SudokuSolver};
+
+/// How many puzzles to try for one of the requested difficulty.
+const MAX_ATTEMPTS: usize = 100;
+
+/// Which clues are removed together, so that the remaining clues form a
+/// pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symmetry {
+    None,
+    /// Symmetric under a half turn around the center.
+    Rotational,
+    /// Symmetric under mirroring left to right.
+    Mirror,
+}
+
+impl Symmetry {
+    /// The cell matching cell `i`.
+    fn partner(self, shape: Shape, i: usize) -> usize {
+        let size = shape.size() as usize;
+        match self {
+            Symmetry::None => i,
+            Symmetry::Rotational => shape.num_cells() - 1 - i,
+            Symmetry::Mirror => i / size * size + size - 1 - i % size,
+        }
+    }
+}
+
+impl FromStr for Symmetry {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Symmetry, String> {
+        match name {
+            "none" => Ok(Symmetry::None),
+            "rotational" => Ok(Symmetry::Rotational),
+            "mirror" => Ok(Symmetry::Mirror),
+            _ => Err(format!(
+                "unknown symmetry '{}', expected one of none, rotational, mirror",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Symmetry::None => "none",
+            Symmetry::Rotational => "rotational",
+            Symmetry::Mirror => "mirror",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub struct Generator {
+    random: Random,
+    shape: Shape,
+    clues: Option<usize>,
+    symmetry: Symmetry,
+    difficulty: Option<Bucket>,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        Generator {
+            random: Random::new(seed),
+            shape: Shape::CLASSIC,
+            clues: None,
+            symmetry: Symmetry::None,
+            difficulty: None,
+        }
+    }
+
+    pub fn shape(mut self, shape: Shape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    /// Stop removing clues at this many. Without a target clues are removed
+    /// until none can be without allowing a second solution.
+    pub fn clues(mut self, clues: Option<usize>) -> Self {
+        self.clues = clues;
+        self
+    }
+
+    pub fn symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    /// Only return puzzles rated in this bucket.
+    pub fn difficulty(mut self, difficulty: Option<Bucket>) -> Self {
+        self.difficulty = difficulty;
+        self
+    }
+
+    /// A new puzzle, or `None` if none of the requested difficulty turned up
+    /// after a number of attempts. Fewer clues than requested may be
+    /// impossible, in which case the puzzle has more.
+    pub fn generate(&mut self) -> Option<Puzzle> {
+        for _ in 0..MAX_ATTEMPTS {
+            let solution = self.full_grid();
+            let puzzle = self.remove_clues(&solution);
+            let wanted = match self.difficulty {
+                Some(difficulty) => {
+                    rating::rate(&puzzle, false).is_ok_and(|rating| rating.bucket == difficulty)
+                }
+                None => true,
+            };
+            if wanted {
+                return Some(puzzle);
+            }
+        }
+        None
+    }
+
+    /// Solves the empty grid with random guesses. Techniques find little on
+    /// an almost empty grid, so they are skipped.
+    fn full_grid(&mut self) -> Vec<i32> {
+        let mut solver = SudokuSolver::new(
+            ObserveableGrid::with_shape(DummyGridObserver {}, self.shape),
+            DummySolverObserver {},
+        )
+        .techniques(&[])
+        .seed(Some(self.random.next_u64()));
+        solver
+            .solve()
+            .outcome
+            .solution()
+            .expect("The empty grid has solutions")
+            .to_vec()
+    }
+
+    fn remove_clues(&mut self, solution: &[i32]) -> Puzzle {
+        let shape = self.shape;
+        let mut puzzle = Puzzle {
+            shape,
+            variants: Vec::new(),
+            cages: Vec::new(),
+            regions: None,
+            constraints: Vec::new(),
+            layout: None,
+            hints: solution.iter().map(|&digit| Some(digit)).collect(),
+        };
+        let mut groups: Vec<Vec<usize>> = (0..shape.num_cells())
+            .filter(|&i| self.symmetry.partner(shape, i) >= i)
+            .map(|i| {
+                let partner = self.symmetry.partner(shape, i);
+                if partner == i {
+                    vec![i]
+                } else {
+                    vec![i, partner]
+                }
+            })
+            .collect();
+        self.random.shuffle(&mut groups);
+        let mut clues = shape.num_cells();
+        for group in groups {
+            if self
+                .clues
+                .is_some_and(|target| clues < target + group.len())
+            {
+                continue;
+            }
+            for &i in &group {
+                puzzle.hints[i] = None;
+            }
+            if crate::count_solutions(&puzzle, 2) == 1 {
+                clues -= group.len();
+            } else {
+                for &i in &group {
+                    puzzle.hints[i] = Some(solution[i]);
+                }
+            }
+            if Some(clues) == self.clues {
+                break;
+            }
+        }
+        puzzle
+    }
+}
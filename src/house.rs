@@ -0,0 +1,233 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The geometry of a grid: `size` rows and columns split into blocks of
+/// `box_width` by `box_height` cells, holding the digits `1..=size`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shape {
+    pub box_width: i32,
+    pub box_height: i32,
+}
+
+impl Shape {
+    /// The classic 9x9 grid with 3x3 blocks.
+    pub const CLASSIC: Shape = Shape {
+        box_width: 3,
+        box_height: 3,
+    };
+
+    /// Largest supported number of digits, which is also the largest size.
+    pub const MAX_SIZE: i32 = 32;
+
+    pub fn new(box_width: i32, box_height: i32) -> Shape {
+        assert!(
+            box_width > 0 && box_height > 0 && box_width * box_height <= Shape::MAX_SIZE,
+            "Unsupported box size {}x{}",
+            box_width,
+            box_height
+        );
+        Shape {
+            box_width,
+            box_height,
+        }
+    }
+
+    /// The usual shape for grids of `size`, with blocks as square as
+    /// possible and wider than high, so 6x6 grids get blocks of 3x2. `None`
+    /// for sizes that only allow blocks of a single row.
+    pub fn for_size(size: i32) -> Option<Shape> {
+        if !(2..=Shape::MAX_SIZE).contains(&size) {
+            return None;
+        }
+        let box_height = (2..=size)
+            .take_while(|height| height * height <= size)
+            .filter(|height| size % height == 0)
+            .last()?;
+        Some(Shape::new(size / box_height, box_height))
+    }
+
+    /// The shape of a grid with `num_cells` cells, see [`Shape::for_size`].
+    pub fn for_cells(num_cells: usize) -> Option<Shape> {
+        let size = (1..=Shape::MAX_SIZE).find(|size| (size * size) as usize == num_cells)?;
+        Shape::for_size(size)
+    }
+
+    /// Number of rows, columns, blocks and digits.
+    pub fn size(&self) -> i32 {
+        self.box_width * self.box_height
+    }
+
+    pub fn num_cells(&self) -> usize {
+        (self.size() * self.size()) as usize
+    }
+
+    /// The cell at `index` in row-major order.
+    pub fn cell(&self, index: usize) -> (i32, i32) {
+        let size = self.size() as usize;
+        ((index % size) as i32, (index / size) as i32)
+    }
+
+    /// The row-major index of the cell at `(x, y)`.
+    pub fn index(&self, x: i32, y: i32) -> usize {
+        (y * self.size() + x) as usize
+    }
+
+    /// The block containing the cell at `(x, y)`.
+    pub fn block(&self, x: i32, y: i32) -> i32 {
+        (y / self.box_height) * self.box_height + x / self.box_width
+    }
+
+    /// The upper left cell of block `b`.
+    pub fn block_origin(&self, b: i32) -> (i32, i32) {
+        (
+            (b % self.box_height) * self.box_width,
+            (b / self.box_height) * self.box_height,
+        )
+    }
+
+    /// Whether two different cells share a house.
+    pub fn sees(&self, a: (i32, i32), b: (i32, i32)) -> bool {
+        a != b && (a.0 == b.0 || a.1 == b.1 || self.block(a.0, a.1) == self.block(b.0, b.1))
+    }
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    /// Parses a grid size such as `16` or `16x16`.
+    fn from_str(name: &str) -> Result<Shape, String> {
+        let size = match name.split_once('x') {
+            Some((rows, columns)) if rows == columns => rows,
+            Some(_) => name,
+            None => name,
+        };
+        size.parse().ok().and_then(Shape::for_size).ok_or_else(|| {
+            format!(
+                "unknown size '{}', expected a grid size such as 4, 6, 9, 12, 16 or 25",
+                name
+            )
+        })
+    }
+}
+
+impl Default for Shape {
+    fn default() -> Shape {
+        Shape::CLASSIC
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.size(), self.size())
+    }
+}
+
+/// A row, column or block, numbered from 0 in reading order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum House {
+    Row(i32),
+    Column(i32),
+    Block(i32),
+    /// The diagonal from the top left to the bottom right corner.
+    Diagonal,
+    /// The diagonal from the top right to the bottom left corner.
+    AntiDiagonal,
+}
+
+impl House {
+    /// The houses containing the cell at `(x, y)`.
+    pub fn of(shape: Shape, x: i32, y: i32) -> [House; 3] {
+        [
+            House::Row(y),
+            House::Column(x),
+            House::Block(shape.block(x, y)),
+        ]
+    }
+
+    /// All houses, blocks first, then rows and columns.
+    pub fn all(shape: Shape) -> impl Iterator<Item = House> {
+        let size = shape.size();
+        (0..size)
+            .map(House::Block)
+            .chain((0..size).map(House::Row))
+            .chain((0..size).map(House::Column))
+    }
+
+    /// The cells of the house in reading order.
+    pub fn cells(self, shape: Shape) -> impl Iterator<Item = (i32, i32)> {
+        let size = shape.size();
+        // Diagonals are one cell wide rectangles shifted sideways each row.
+        let ((mut left, top), width, height, shift) = match self {
+            House::Row(y) => ((0, y), size, 1, 0),
+            House::Column(x) => ((x, 0), 1, size, 0),
+            House::Block(b) => (shape.block_origin(b), shape.box_width, shape.box_height, 0),
+            House::Diagonal => ((0, 0), 1, size, 1),
+            House::AntiDiagonal => ((size - 1, 0), 1, size, -1),
+        };
+        let (mut x, mut y) = (left, top);
+        std::iter::from_fn(move || {
+            if y == top + height {
+                return None;
+            }
+            let cell = (x, y);
+            x += 1;
+            if x == left + width {
+                left += shift;
+                x = left;
+                y += 1;
+            }
+            Some(cell)
+        })
+    }
+
+    pub fn contains(&self, shape: Shape, x: i32, y: i32) -> bool {
+        match self {
+            House::Diagonal => x == y,
+            House::AntiDiagonal => x + y == shape.size() - 1,
+            _ => House::of(shape, x, y).contains(self),
+        }
+    }
+}
+
+impl fmt::Display for House {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            House::Row(y) => write!(f, "row {}", y + 1),
+            House::Column(x) => write!(f, "column {}", x + 1),
+            House::Block(b) => write!(f, "block {}", b + 1),
+            House::Diagonal => write!(f, "the diagonal"),
+            House::AntiDiagonal => write!(f, "the anti-diagonal"),
+        }
+    }
+}
+
+/// The usual `r1c1` name of the cell at `(x, y)`.
+pub fn cell_name(x: i32, y: i32) -> String {
+    format!("r{}c{}", y + 1, x + 1)
+}
+
+/// The cell at `(x, y)` named like `r1c1`, the inverse of [`cell_name`].
+/// Upper case letters are accepted too.
+pub fn parse_cell_name(name: &str) -> Option<(i32, i32)> {
+    let name = name.to_ascii_lowercase();
+    let (row, column) = name.strip_prefix('r')?.split_once('c')?;
+    match (row.parse::<i32>(), column.parse::<i32>()) {
+        (Ok(row), Ok(column)) if row > 0 && column > 0 => Some((column - 1, row - 1)),
+        _ => None,
+    }
+}
+
+/// The character for `digit`: `1` to `9`, then `A` for 10, `B` for 11 and
+/// so on.
+pub fn digit_char(digit: i32) -> char {
+    std::char::from_digit(digit as u32, 36).map_or('?', |c| c.to_ascii_uppercase())
+}
+
+/// The digit written as `c`, the inverse of [`digit_char`]. Letters may be
+/// lower case.
+pub fn char_digit(c: char) -> Option<i32> {
+    match c.to_digit(36) {
+        Some(digit) if digit > 0 => Some(digit as i32),
+        _ => None,
+    }
+}
@@ -0,0 +1,123 @@
+//! A reasonably fast and efficient sudoku solver.
+//!
+//! Puzzles are parsed with [`parse`] or streamed from any [`std::io::BufRead`]
+//! with [`reader::read`], checked for contradicting hints with [`validate()`]
+//! and solved with [`solve`]. [`solve_observed`] reports the progress of the
+//! solver to a [`GridObserver`] and a [`SolverObserver`], for example the
+//! terminal visualization in [`observer`]. [`DlxSolver`] is an alternative
+//! [`Solver`] searching for an exact cover with dancing links. [`rate`] rates
+//! how hard a puzzle is for a human solver and [`hint`] explains the next
+//! logical step. New puzzles are made with a [`Generator`] and reduced to the
+//! clues they need with [`minimize()`]. Grids of other sizes, from 4x4 up to
+//! 25x25 with letters for the digits above 9, are described by a [`Shape`],
+//! and puzzles made of several overlapping grids by a [`Layout`].
+//! Thermometers, arrows and other constraints between cells implement
+//! [`constraint::Constraint`].
+//!
+//! ```
+//! let puzzle = sudokusolve::parse(
+//!     ".785.36.4..5.487.2.6.....85.......476.1.75..87.2.143...27...4.1...1.2....564...2.",
+//! )
+//! .unwrap();
+//! assert!(sudokusolve::validate(&puzzle).is_empty());
+//! let report = sudokusolve::solve(&puzzle);
+//! assert!(report.outcome.solution().is_some());
+//! ```
+
+extern crate bitmaps;
+extern crate serde_json;
+extern crate termion;
+
+use std::rc::Rc;
+
+pub mod cell;
+pub mod constraint;
+pub mod dlx;
+pub mod generator;
+pub mod house;
+pub mod minimize;
+pub mod multigrid;
+pub mod observer;
+pub mod random;
+pub mod rating;
+pub mod reader;
+pub mod rules;
+pub mod solver;
+pub mod strategy;
+pub mod validate;
+pub mod writer;
+
+pub use crate::cell::{Cell, EliminationError};
+pub use crate::dlx::DlxSolver;
+pub use crate::generator::{Generator, Symmetry};
+pub use crate::house::{House, Shape};
+pub use crate::minimize::{is_minimal, minimize};
+pub use crate::multigrid::{Layout, MultiGrid};
+pub use crate::observer::{DummyGridObserver, DummySolverObserver, GridObserver, SolverObserver};
+pub use crate::rating::{rate, Bucket, Rating};
+pub use crate::reader::{ParseError, ParseErrorReason, Puzzle, Puzzles, ReadError};
+pub use crate::solver::{
+    Backend, Grid, ObserveableGrid, SolveOutcome, SolveReport, SolveStats, Solver, SudokuSolver,
+};
+pub use crate::strategy::{hint, Candidates, Deduction, PuzzleError, Technique, TechniqueGroup};
+pub use crate::validate::Conflict;
+
+/// Parses a single puzzle line of 81 characters with `.` for blanks, or as
+/// many as another grid size has cells. Use [`reader::read`] for other
+/// formats.
+pub fn parse(line: &str) -> Result<Puzzle, ParseError> {
+    line.parse()
+}
+
+/// Pairs of hints in `puzzle` that contradict each other.
+pub fn validate(puzzle: &Puzzle) -> Vec<Conflict> {
+    puzzle.conflicts()
+}
+
+/// Solves `puzzle`, returning its first solution.
+pub fn solve(puzzle: &Puzzle) -> SolveReport {
+    solve_observed(puzzle, DummyGridObserver {}, DummySolverObserver {})
+}
+
+/// Solves `puzzle` while reporting progress to the observers.
+pub fn solve_observed<TGridObserver: GridObserver, TSolverObserver: SolverObserver>(
+    puzzle: &Puzzle,
+    grid_observer: TGridObserver,
+    solver_observer: TSolverObserver,
+) -> SolveReport {
+    let rules = Rc::new(puzzle.rules());
+    match &puzzle.layout {
+        Some(layout) => {
+            let grid = MultiGrid::new(grid_observer, Rc::new(layout.clone()), rules);
+            let mut solver = SudokuSolver::new(grid, solver_observer);
+            puzzle.apply(&mut solver);
+            solver.solve()
+        }
+        None => {
+            let grid = ObserveableGrid::with_rules(grid_observer, rules);
+            let mut solver = SudokuSolver::new(grid, solver_observer);
+            puzzle.apply(&mut solver);
+            solver.solve()
+        }
+    }
+}
+
+/// Counts the solutions of `puzzle`, stopping once `limit` are found. Only
+/// singles are used between guesses, the other techniques take longer than
+/// the guesses they save, especially on larger grids.
+pub fn count_solutions(puzzle: &Puzzle, limit: usize) -> usize {
+    let rules = Rc::new(puzzle.rules());
+    let singles = [Technique::HiddenSingle, Technique::NakedSingle];
+    let mut solver: Box<dyn Solver> = match &puzzle.layout {
+        Some(layout) => {
+            let grid = MultiGrid::new(DummyGridObserver {}, Rc::new(layout.clone()), rules);
+            Box::new(SudokuSolver::new(grid, DummySolverObserver {}).techniques(&singles))
+        }
+        None => {
+            let grid = ObserveableGrid::with_rules(DummyGridObserver {}, rules);
+            Box::new(SudokuSolver::new(grid, DummySolverObserver {}).techniques(&singles))
+        }
+    };
+    puzzle.apply(&mut *solver);
+    solver.count_solutions(limit)
+}
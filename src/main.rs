@@ -1,73 +1,564 @@
-extern crate bitmaps;
 extern crate clap;
+extern crate sudokusolve;
 extern crate termion;
 
-mod cell;
-mod observer;
-mod reader;
-mod solver;
-mod writer;
-
 use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::process::ExitCode;
-
-use crate::observer::{DummyGridObserver, DummySolverObserver, TermObserver, TermSolverObserver};
-use crate::solver::{ObserveableGrid, Solver, SudokuSolver};
+use std::rc::Rc;
+use std::time::Instant;
 
 use clap::Clap;
+use sudokusolve::dlx::DlxSolver;
+use sudokusolve::generator::{Generator, Symmetry};
+use sudokusolve::house::{self, Shape};
+use sudokusolve::multigrid::MultiGrid;
+use sudokusolve::observer::{
+    DummyGridObserver, DummySolverObserver, SolverObserver, TermObserver, TermSolverObserver,
+};
+use sudokusolve::random::Random;
+use sudokusolve::rating::{self, Bucket};
+use sudokusolve::reader::{self, Puzzle, ReadError};
+use sudokusolve::rules::Variant;
+use sudokusolve::solver::{
+    Backend, Grid, ObserveableGrid, SolveOutcome, SolveReport, SolveStats, Solver, SudokuSolver,
+};
+use sudokusolve::strategy::{self, Candidates, Technique, TechniqueGroup};
+use sudokusolve::writer;
+
+#[derive(Clap)]
+struct InputOpts {
+    /// Format of the input files: auto, line, zeros, grid, sdk, ss or json
+    #[clap(long, default_value = "auto")]
+    input_format: reader::Format,
+    /// Variant rules of the puzzles until a file declares its own:
+    /// diagonal, anti-knight, anti-king or non-consecutive. May be repeated
+    #[clap(long, number_of_values = 1)]
+    variant: Vec<Variant>,
+    /// Files to read puzzles from, '-' for stdin (default)
+    inputs: Vec<String>,
+}
 
 #[derive(Clap)]
 struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// Do not draw the progress of the solver. It is not drawn either when
+    /// solutions are written to stdout and stdout is not a terminal
     #[clap(short, long)]
     no_observe: bool,
+    /// Search engine: elimination, which uses the logical techniques and
+    /// draws its progress, or dlx, an exact cover search with dancing links
+    /// that draws nothing
+    #[clap(long, default_value = "elimination")]
+    backend: Backend,
+    /// Report puzzles with multiple solutions instead of solving them
+    #[clap(short = 'u', long)]
+    check_unique: bool,
+    /// Only eliminate solved cells from their peers before guessing, without
+    /// any other logical techniques
+    #[clap(long)]
+    no_techniques: bool,
+    /// More logical techniques to try after singles and intersections, which
+    /// are slower to look for: subsets, fish, chains or all. May be repeated
+    #[clap(long, number_of_values = 1, conflicts_with = "no-techniques")]
+    techniques: Vec<TechniqueGroup>,
+    /// Also use techniques that only hold for puzzles with a single
+    /// solution, like unique rectangles. Ignored with -u
+    #[clap(long)]
+    assume_unique: bool,
+    /// Give up on a puzzle after this many guesses
+    #[clap(long)]
+    max_guesses: Option<usize>,
+    /// Guess cells and digits in a random order given by this seed
+    #[clap(long)]
+    seed: Option<u64>,
+    /// File to write solutions to, '-' for stdout
+    #[clap(short, long, default_value = "-")]
+    output: String,
+    /// Format of the solutions: line, grid, csv or jsonl
+    #[clap(long, default_value = "line")]
+    output_format: writer::Format,
+    #[clap(flatten)]
+    input: InputOpts,
 }
 
-fn main() -> ExitCode {
-    let opts = Opts::parse();
+#[derive(Clap)]
+enum Command {
+    /// Check puzzles for hints that contradict each other
+    Validate(ValidateOpts),
+    /// Rate how hard puzzles are to solve with logical techniques
+    Rate(RateOpts),
+    /// Explain the next logical step of puzzles
+    Hint(HintOpts),
+    /// Generate new puzzles with a single solution
+    Generate(GenerateOpts),
+    /// Remove clues that are not needed for a single solution
+    Minimize(MinimizeOpts),
+}
+
+#[derive(Clap)]
+struct ValidateOpts {
+    #[clap(flatten)]
+    input: InputOpts,
+}
+
+#[derive(Clap)]
+struct RateOpts {
+    /// Also use techniques that only hold for puzzles with a single solution
+    #[clap(long)]
+    assume_unique: bool,
+    #[clap(flatten)]
+    input: InputOpts,
+}
+
+#[derive(Clap)]
+struct HintOpts {
+    /// Also use techniques that only hold for puzzles with a single solution
+    #[clap(long)]
+    assume_unique: bool,
+    /// Explain every step until the puzzle is solved instead of only the
+    /// next one
+    #[clap(long)]
+    all: bool,
+    #[clap(flatten)]
+    input: InputOpts,
+}
+
+#[derive(Clap)]
+struct GenerateOpts {
+    /// Number of puzzles to generate
+    #[clap(long, default_value = "1")]
+    count: usize,
+    /// Stop removing clues at this many
+    #[clap(long)]
+    clues: Option<usize>,
+    /// Size of the grid, such as 4, 6, 9 or 16
+    #[clap(long, default_value = "9")]
+    size: Shape,
+    /// Symmetry of the clues: none, rotational or mirror
+    #[clap(long, default_value = "none")]
+    symmetry: Symmetry,
+    /// Only keep puzzles rated easy, medium, hard, fiendish or diabolical
+    #[clap(long)]
+    difficulty: Option<Bucket>,
+    /// Seed for repeatable output, taken from the clock if not given
+    #[clap(long)]
+    seed: Option<u64>,
+    /// File to write puzzles to, '-' for stdout
+    #[clap(short, long, default_value = "-")]
+    output: String,
+}
 
-    let output_filename = "output";
-    if let Err(err) = File::create(output_filename) {
-        println!("Failed to create file '{}', ({})", output_filename, err);
-        return 1.into();
+#[derive(Clap)]
+struct MinimizeOpts {
+    /// Only report whether every clue is needed
+    #[clap(long)]
+    check: bool,
+    /// File to write the reduced puzzles to, '-' for stdout
+    #[clap(short, long, default_value = "-")]
+    output: String,
+    #[clap(flatten)]
+    input: InputOpts,
+}
+
+fn open_input(filename: &str) -> io::Result<Box<dyn BufRead>> {
+    if filename == "-" {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(filename)?)))
     }
-    for i in 0..1011 {
-        let mut solver: Box<dyn Solver> = if !opts.no_observe {
-            let observer = TermObserver::new();
-            let grid = ObserveableGrid::new(observer);
-            let solver_observer = TermSolverObserver::new();
-            Box::new(SudokuSolver::new(grid, solver_observer))
-        } else {
-            let observer = DummyGridObserver {};
-            let grid = ObserveableGrid::new(observer);
-            let solver_observer = DummySolverObserver {};
-            Box::new(SudokuSolver::new(grid, solver_observer))
-        };
-        let input_filename = "testdata/easy";
-        let solution = match reader::read(input_filename, &mut *solver, i) {
-            Ok(()) => solver.solve(),
+}
+
+fn open_output(filename: &str) -> io::Result<Box<dyn Write>> {
+    if filename == "-" {
+        Ok(Box::new(io::stdout()))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(filename)?)))
+    }
+}
+
+/// Calls `f` with the case number of every puzzle in the inputs. Invalid
+/// puzzles are reported and skipped, the number of them is returned.
+fn for_each_puzzle<F>(opts: &InputOpts, mut f: F) -> Result<usize, ExitCode>
+where
+    F: FnMut(usize, &Puzzle) -> Result<(), ExitCode>,
+{
+    let stdin = ["-".to_string()];
+    let inputs = if opts.inputs.is_empty() {
+        &stdin[..]
+    } else {
+        &opts.inputs[..]
+    };
+    let mut i = 0;
+    let mut num_invalid = 0;
+    for input_filename in inputs {
+        let input = match open_input(input_filename) {
+            Ok(input) => input,
             Err(err) => {
-                println!(
-                    "Case {}: Failed to read file from file '{}', ({})",
-                    i, input_filename, err
-                );
-                return 1.into();
+                eprintln!("Failed to open file '{}', ({})", input_filename, err);
+                return Err(1.into());
             }
         };
-        match solution {
-            Some(solution) => match writer::write(output_filename, &solution) {
-                Ok(_) => {}
+        let format = match opts.input_format {
+            reader::Format::Auto => reader::Format::from_extension(input_filename),
+            format => format,
+        };
+        for puzzle in reader::read(input, format).variants(&opts.variant) {
+            match puzzle {
+                Ok(puzzle) => f(i, &puzzle)?,
+                Err(ReadError::Parse(err)) => {
+                    eprintln!(
+                        "Case {}: Invalid puzzle in file '{}', {}",
+                        i, input_filename, err
+                    );
+                    num_invalid += 1;
+                }
                 Err(err) => {
-                    println!(
-                        "Case {}: Failed to write to file '{}', ({})",
-                        i, output_filename, err
+                    eprintln!(
+                        "Case {}: Failed to read from file '{}', ({})",
+                        i, input_filename, err
                     );
-                    return 1.into();
+                    return Err(1.into());
+                }
+            };
+            i += 1;
+        }
+    }
+    Ok(num_invalid)
+}
+
+/// A solver for `grid` set up with the options of the command line.
+fn configure<TGrid: Grid + 'static, TObserver: SolverObserver + 'static>(
+    opts: &Opts,
+    grid: TGrid,
+    observer: TObserver,
+) -> Box<dyn Solver> {
+    let techniques = if opts.no_techniques {
+        Vec::new()
+    } else {
+        TechniqueGroup::techniques(&opts.techniques)
+    };
+    Box::new(
+        SudokuSolver::new(grid, observer)
+            .techniques(&techniques)
+            .check_unique(opts.check_unique)
+            .assume_unique(opts.assume_unique)
+            .guess_limit(opts.max_guesses)
+            .seed(opts.seed),
+    )
+}
+
+/// A solver for `puzzle`, drawing its progress unless `-n` is given.
+fn solver_for(opts: &Opts, puzzle: &Puzzle) -> Box<dyn Solver> {
+    let rules = puzzle.rules();
+    if opts.backend == Backend::Dlx {
+        let solver = match &puzzle.layout {
+            Some(layout) => DlxSolver::with_layout(layout, Rc::new(rules)),
+            None => DlxSolver::new(Rc::new(rules)),
+        };
+        return Box::new(
+            solver
+                .check_unique(opts.check_unique)
+                .guess_limit(opts.max_guesses)
+                .seed(opts.seed),
+        );
+    }
+    // The drawing would end up among the solutions.
+    let no_observe = opts.no_observe || (opts.output == "-" && !termion::is_tty(&io::stdout()));
+    match (&puzzle.layout, no_observe) {
+        (Some(layout), false) => {
+            let observer = TermObserver::with_rules(&rules);
+            let grid = MultiGrid::new(observer, Rc::new(layout.clone()), Rc::new(rules));
+            configure(opts, grid, TermSolverObserver::with_layout(layout))
+        }
+        (Some(layout), true) => {
+            let grid = MultiGrid::new(
+                DummyGridObserver {},
+                Rc::new(layout.clone()),
+                Rc::new(rules),
+            );
+            configure(opts, grid, DummySolverObserver {})
+        }
+        (None, false) => {
+            let observer = TermObserver::with_rules(&rules);
+            let grid = ObserveableGrid::with_rules(observer, Rc::new(rules));
+            configure(opts, grid, TermSolverObserver::with_shape(puzzle.shape))
+        }
+        (None, true) => {
+            let grid = ObserveableGrid::with_rules(DummyGridObserver {}, Rc::new(rules));
+            configure(opts, grid, DummySolverObserver {})
+        }
+    }
+}
+
+fn solve(opts: &Opts) -> Result<(), ExitCode> {
+    let mut output = match open_output(&opts.output) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("Failed to create file '{}', ({})", opts.output, err);
+            return Err(1.into());
+        }
+    };
+    let write_error = |err| {
+        eprintln!("Failed to write to file '{}', ({})", opts.output, err);
+        ExitCode::from(1)
+    };
+    writer::write_header(&mut *output, opts.output_format).map_err(write_error)?;
+    let mut num_failed = 0;
+    let num_unparsed = for_each_puzzle(&opts.input, |i, puzzle| {
+        if let Err(message) = writer::check(opts.output_format, puzzle) {
+            eprintln!("Case {}: {}", i, message);
+            num_failed += 1;
+            return Ok(());
+        }
+        let start = Instant::now();
+        let conflicts = puzzle.conflicts();
+        let report = if conflicts.is_empty() {
+            let mut solver = solver_for(opts, puzzle);
+            puzzle.apply(&mut *solver);
+            solver.solve()
+        } else {
+            for conflict in &conflicts {
+                eprintln!("Case {}: {}", i, conflict);
+            }
+            SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats: SolveStats::default(),
+            }
+        };
+        let record = writer::Record {
+            case: i,
+            puzzle,
+            outcome: &report.outcome,
+            stats: &report.stats,
+            elapsed: start.elapsed(),
+        };
+        match report.outcome {
+            SolveOutcome::Solved(_) => {}
+            SolveOutcome::Unsolvable => eprintln!("Case {}: Puzzle has no solution.", i),
+            SolveOutcome::MultipleSolutions(_, _) => {
+                eprintln!("Case {}: Puzzle has multiple solutions.", i)
+            }
+            SolveOutcome::Aborted => eprintln!("Case {}: Gave up solving.", i),
+        }
+        writer::write(&mut *output, opts.output_format, &record).map_err(write_error)
+    })?;
+    output.flush().map_err(write_error)?;
+    if num_failed + num_unparsed > 0 {
+        return Err(1.into());
+    }
+    Ok(())
+}
+
+fn validate(opts: &ValidateOpts) -> Result<(), ExitCode> {
+    let mut num_conflicting = 0;
+    let num_unparsed = for_each_puzzle(&opts.input, |i, puzzle| {
+        let conflicts = puzzle.conflicts();
+        if conflicts.is_empty() {
+            println!("Case {}: ok", i);
+        } else {
+            num_conflicting += 1;
+        }
+        for conflict in conflicts {
+            println!("Case {}: {}", i, conflict);
+        }
+        Ok(())
+    })?;
+    if num_conflicting + num_unparsed > 0 {
+        return Err(1.into());
+    }
+    Ok(())
+}
+
+fn rate(opts: &RateOpts) -> Result<(), ExitCode> {
+    let mut num_failed = 0;
+    let num_unparsed = for_each_puzzle(&opts.input, |i, puzzle| {
+        if puzzle.layout.is_some() {
+            eprintln!("Case {}: Puzzles with a layout cannot be rated.", i);
+            num_failed += 1;
+            return Ok(());
+        }
+        let conflicts = puzzle.conflicts();
+        for conflict in &conflicts {
+            eprintln!("Case {}: {}", i, conflict);
+        }
+        match rating::rate(puzzle, opts.assume_unique) {
+            Ok(rating) if conflicts.is_empty() => println!("Case {}: {}", i, rating),
+            _ => {
+                eprintln!("Case {}: Puzzle has no solution.", i);
+                num_failed += 1;
+            }
+        }
+        Ok(())
+    })?;
+    if num_failed + num_unparsed > 0 {
+        return Err(1.into());
+    }
+    Ok(())
+}
+
+fn hint(opts: &HintOpts) -> Result<(), ExitCode> {
+    let mut num_failed = 0;
+    let num_unparsed = for_each_puzzle(&opts.input, |i, puzzle| {
+        if puzzle.layout.is_some() {
+            eprintln!("Case {}: Puzzles with a layout cannot be explained.", i);
+            num_failed += 1;
+            return Ok(());
+        }
+        let mut candidates = match Candidates::from_puzzle(puzzle) {
+            Ok(candidates) => candidates,
+            Err(_) => {
+                for conflict in puzzle.conflicts() {
+                    eprintln!("Case {}: {}", i, conflict);
                 }
-            },
+                eprintln!("Case {}: Puzzle has no solution.", i);
+                num_failed += 1;
+                return Ok(());
+            }
+        };
+        loop {
+            if candidates.is_solved() {
+                println!("Case {}: Solved.", i);
+                break;
+            }
+            let deduction =
+                match strategy::next_deduction(&candidates, Technique::ALL, opts.assume_unique) {
+                    Some(deduction) => deduction,
+                    None => {
+                        println!("Case {}: No logical step found.", i);
+                        break;
+                    }
+                };
+            println!("Case {}: {}", i, deduction);
+            if !opts.all {
+                break;
+            }
+            if candidates.apply(&deduction).is_err() {
+                eprintln!("Case {}: Puzzle has no solution.", i);
+                num_failed += 1;
+                break;
+            }
+        }
+        Ok(())
+    })?;
+    if num_failed + num_unparsed > 0 {
+        return Err(1.into());
+    }
+    Ok(())
+}
+
+fn generate(opts: &GenerateOpts) -> Result<(), ExitCode> {
+    let mut output = match open_output(&opts.output) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("Failed to create file '{}', ({})", opts.output, err);
+            return Err(1.into());
+        }
+    };
+    let write_error = |err| {
+        eprintln!("Failed to write to file '{}', ({})", opts.output, err);
+        ExitCode::from(1)
+    };
+    let seed = match opts.seed {
+        Some(seed) => seed,
+        None => {
+            let (_, seed) = Random::from_time();
+            eprintln!("Seed: {}", seed);
+            seed
+        }
+    };
+    let mut generator = Generator::new(seed)
+        .shape(opts.size)
+        .clues(opts.clues)
+        .symmetry(opts.symmetry)
+        .difficulty(opts.difficulty);
+    for i in 0..opts.count {
+        match generator.generate() {
+            Some(puzzle) => writeln!(output, "{}", puzzle).map_err(write_error)?,
             None => {
-                println!("Case {}: Failed to solve.", i)
+                eprintln!(
+                    "Case {}: Found no {} puzzle.",
+                    i,
+                    opts.difficulty.map_or(String::new(), |d| d.to_string())
+                );
+                return Err(1.into());
             }
+        }
+    }
+    output.flush().map_err(write_error)
+}
+
+fn minimize(opts: &MinimizeOpts) -> Result<(), ExitCode> {
+    let mut output = match open_output(&opts.output) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("Failed to create file '{}', ({})", opts.output, err);
+            return Err(1.into());
+        }
+    };
+    let write_error = |err| {
+        eprintln!("Failed to write to file '{}', ({})", opts.output, err);
+        ExitCode::from(1)
+    };
+    let mut num_failed = 0;
+    let num_unparsed = for_each_puzzle(&opts.input, |i, puzzle| {
+        let solutions = if puzzle.conflicts().is_empty() {
+            sudokusolve::count_solutions(puzzle, 2)
+        } else {
+            0
         };
+        if solutions != 1 {
+            if solutions == 0 {
+                eprintln!("Case {}: Puzzle has no solution.", i);
+            } else {
+                eprintln!("Case {}: Puzzle has multiple solutions.", i);
+            }
+            num_failed += 1;
+            return Ok(());
+        }
+        if opts.check {
+            let redundant = sudokusolve::minimize::redundant_clues(puzzle);
+            if redundant.is_empty() {
+                writeln!(output, "Case {}: minimal", i).map_err(write_error)
+            } else {
+                let cells: Vec<String> = redundant
+                    .iter()
+                    .map(|&(x, y)| house::cell_name(x, y))
+                    .collect();
+                writeln!(
+                    output,
+                    "Case {}: not minimal, {} can be removed",
+                    i,
+                    cells.join(", ")
+                )
+                .map_err(write_error)
+            }
+        } else {
+            let reduced =
+                sudokusolve::minimize::minimize(puzzle).expect("Puzzle has a single solution");
+            writeln!(output, "{}", reduced).map_err(write_error)
+        }
+    })?;
+    output.flush().map_err(write_error)?;
+    if num_failed + num_unparsed > 0 {
+        return Err(1.into());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let opts = Opts::parse();
+    let result = match &opts.command {
+        Some(Command::Validate(validate_opts)) => validate(validate_opts),
+        Some(Command::Rate(rate_opts)) => rate(rate_opts),
+        Some(Command::Hint(hint_opts)) => hint(hint_opts),
+        Some(Command::Generate(generate_opts)) => generate(generate_opts),
+        Some(Command::Minimize(minimize_opts)) => minimize(minimize_opts),
+        None => solve(&opts),
+    };
+    match result {
+        Ok(()) => 0.into(),
+        Err(code) => code,
     }
-    0.into()
 }
@@ -0,0 +1,54 @@
+//! Minimality of puzzles: whether every clue is needed for the solution to
+//! be unique, and removing the clues that are not.
+
+use crate::count_solutions;
+use crate::reader::Puzzle;
+
+fn has_single_solution(puzzle: &Puzzle) -> bool {
+    puzzle.conflicts().is_empty() && count_solutions(puzzle, 2) == 1
+}
+
+/// The clues that could each be removed on their own while keeping a single
+/// solution, as `(x, y)`. Empty for puzzles without a single solution.
+pub fn redundant_clues(puzzle: &Puzzle) -> Vec<(i32, i32)> {
+    if !has_single_solution(puzzle) {
+        return Vec::new();
+    }
+    let mut reduced = puzzle.clone();
+    let mut redundant = Vec::new();
+    for i in 0..puzzle.hints.len() {
+        if let Some(digit) = puzzle.hints[i] {
+            reduced.hints[i] = None;
+            if count_solutions(&reduced, 2) == 1 {
+                redundant.push(puzzle.cell(i));
+            }
+            reduced.hints[i] = Some(digit);
+        }
+    }
+    redundant
+}
+
+/// Whether the puzzle has a single solution and loses it without any one
+/// of its clues.
+pub fn is_minimal(puzzle: &Puzzle) -> bool {
+    has_single_solution(puzzle) && redundant_clues(puzzle).is_empty()
+}
+
+/// Removes clues in order while the puzzle keeps a single solution, or
+/// `None` if it does not have one to begin with. A clue that is needed stays
+/// needed with fewer clues, so one pass leaves a minimal puzzle.
+pub fn minimize(puzzle: &Puzzle) -> Option<Puzzle> {
+    if !has_single_solution(puzzle) {
+        return None;
+    }
+    let mut reduced = puzzle.clone();
+    for i in 0..reduced.hints.len() {
+        if let Some(digit) = reduced.hints[i] {
+            reduced.hints[i] = None;
+            if count_solutions(&reduced, 2) != 1 {
+                reduced.hints[i] = Some(digit);
+            }
+        }
+    }
+    Some(reduced)
+}
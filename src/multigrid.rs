@@ -0,0 +1,340 @@
+//! Puzzles made of several overlapping grids, like the five of a Samurai
+//! sudoku, which share the cells where they overlap.
+//!
+//! The grids sit on a canvas and every one of them follows the same rules.
+//! A digit placed in a shared cell is eliminated from the houses of each
+//! grid holding it, which is how the grids constrain each other.
+
+use std::rc::Rc;
+
+use crate::cell::{Cell, EliminationError};
+use crate::house::Shape;
+use crate::observer::GridObserver;
+use crate::rules::Rules;
+use crate::solver::Grid;
+use crate::strategy::Candidates;
+
+/// Where the grids of a puzzle sit on the canvas, each one given by the
+/// position of its top left cell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    shape: Shape,
+    origins: Vec<(i32, i32)>,
+    width: i32,
+    height: i32,
+    /// The cells covered by a grid in row-major order.
+    cells: Vec<(i32, i32)>,
+    /// The index in `cells` of every cell of the canvas, `None` where no grid
+    /// covers it.
+    indices: Vec<Option<usize>>,
+}
+
+impl Layout {
+    /// The names of the layouts of classic grids known to `parse_origins`.
+    pub const NAMES: [&'static str; 4] = ["samurai", "twodoku", "butterfly", "sohei"];
+
+    /// The origins of the grids of a named layout, or of a list of origins
+    /// such as `0,0 6,6`, written as column and row counted from 0.
+    pub fn parse_origins(name: &str) -> Result<Vec<(i32, i32)>, String> {
+        match name.trim() {
+            "samurai" => Ok(vec![(0, 0), (12, 0), (6, 6), (0, 12), (12, 12)]),
+            "twodoku" => Ok(vec![(0, 0), (6, 6)]),
+            "butterfly" => Ok(vec![(0, 0), (3, 0), (0, 3), (3, 3)]),
+            "sohei" => Ok(vec![(6, 0), (0, 6), (12, 6), (6, 12)]),
+            list => list
+                .split_whitespace()
+                .map(|origin| {
+                    let mut coordinates = origin.splitn(2, ',').map(str::parse::<i32>);
+                    match (coordinates.next(), coordinates.next()) {
+                        (Some(Ok(x)), Some(Ok(y))) if x >= 0 && y >= 0 => Ok((x, y)),
+                        _ => Err(format!(
+                            "unknown layout '{}', expected one of {} or grid origins like '0,0 6,6'",
+                            origin,
+                            Layout::NAMES.join(", ")
+                        )),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Lays out grids of `shape` at `origins`, which must line up with the
+    /// blocks so that the grids overlap in whole blocks.
+    pub fn new(shape: Shape, origins: &[(i32, i32)]) -> Result<Layout, String> {
+        if origins.is_empty() {
+            return Err("the layout has no grids".to_string());
+        }
+        for (g, &(x, y)) in origins.iter().enumerate() {
+            if x % shape.box_width != 0 || y % shape.box_height != 0 {
+                return Err(format!(
+                    "grid {} at {},{} does not line up with the blocks",
+                    g + 1,
+                    x,
+                    y
+                ));
+            }
+            if origins[..g].contains(&(x, y)) {
+                return Err(format!("two grids are at {},{}", x, y));
+            }
+        }
+        let size = shape.size();
+        let width = origins.iter().map(|&(x, _)| x + size).max().unwrap();
+        let height = origins.iter().map(|&(_, y)| y + size).max().unwrap();
+        // Sets of cells hold up to 1024, numbered across the canvas.
+        if width * height > 1024 {
+            return Err(format!(
+                "a canvas of {}x{} cells is too large, at most 1024 are supported",
+                width, height
+            ));
+        }
+        let mut cells = Vec::new();
+        let mut indices = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let covered = origins
+                    .iter()
+                    .any(|&(ox, oy)| (ox..ox + size).contains(&x) && (oy..oy + size).contains(&y));
+                if covered {
+                    indices.push(Some(cells.len()));
+                    cells.push((x, y));
+                } else {
+                    indices.push(None);
+                }
+            }
+        }
+        Ok(Layout {
+            shape,
+            origins: origins.to_vec(),
+            width,
+            height,
+            cells,
+            indices,
+        })
+    }
+
+    /// The shape of every grid.
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    /// The top left cell of every grid.
+    pub fn origins(&self) -> &[(i32, i32)] {
+        &self.origins
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Cells covered by at least one grid.
+    pub fn num_cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// The covered cell numbered `index` in row-major order.
+    pub fn cell(&self, index: usize) -> (i32, i32) {
+        self.cells[index]
+    }
+
+    /// The number of a covered cell in row-major order.
+    pub fn index(&self, x: i32, y: i32) -> usize {
+        self.indices[(y * self.width + x) as usize].expect("Cell is covered by a grid")
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        (0..self.width).contains(&x)
+            && (0..self.height).contains(&y)
+            && self.indices[(y * self.width + x) as usize].is_some()
+    }
+
+    /// The grids holding the cell at `(x, y)`, with the position of the cell
+    /// within each of them.
+    pub fn grids_at(&self, x: i32, y: i32) -> impl Iterator<Item = (usize, i32, i32)> + '_ {
+        let size = self.shape.size();
+        self.origins
+            .iter()
+            .enumerate()
+            .filter(move |&(_, &(ox, oy))| {
+                (ox..ox + size).contains(&x) && (oy..oy + size).contains(&y)
+            })
+            .map(move |(g, &(ox, oy))| (g, x - ox, y - oy))
+    }
+
+    /// The values of grid `g` in its own row-major order, picked from values
+    /// given for every covered cell.
+    pub fn grid_values<T: Clone>(&self, g: usize, values: &[T]) -> Vec<T> {
+        let (ox, oy) = self.origins[g];
+        (0..self.shape.num_cells())
+            .map(|i| {
+                let (x, y) = self.shape.cell(i);
+                values[self.index(ox + x, oy + y)].clone()
+            })
+            .collect()
+    }
+}
+
+/// The cells of all grids of a layout, where eliminating a digit around a
+/// shared cell reaches into every grid holding it.
+#[derive(Clone, Debug)]
+pub struct MultiGrid<TObserver: GridObserver> {
+    layout: Rc<Layout>,
+    /// The rules every grid follows.
+    rules: Rc<Rules>,
+    cells: Vec<Cell>, // One per covered cell of the layout
+    observer: TObserver,
+}
+
+impl<TObserver: GridObserver> MultiGrid<TObserver> {
+    pub fn new(observer: TObserver, layout: Rc<Layout>, rules: Rc<Rules>) -> MultiGrid<TObserver> {
+        MultiGrid {
+            cells: (0..layout.num_cells())
+                .map(|_| Cell::with_digits(layout.shape().size()))
+                .collect(),
+            layout,
+            rules,
+            observer,
+        }
+    }
+
+    fn eliminate_in_cell<F: FnMut((i32, i32))>(
+        &mut self,
+        x: i32,
+        y: i32,
+        digit: i32,
+        mark_solved: &mut F,
+    ) -> Result<bool, EliminationError> {
+        self.observer.highlight_cell(x, y, self.cell(x, y), false);
+        let eliminated = self.cell_mut(x, y).eliminate_possible(digit)?;
+        self.observer.clear_cell(x, y, self.cell(x, y));
+        if self.cell(x, y).num_possibles() == 1 {
+            mark_solved((x, y));
+        }
+        Ok(eliminated)
+    }
+}
+
+impl<TObserver: GridObserver> Grid for MultiGrid<TObserver> {
+    /// The shape of each grid.
+    fn shape(&self) -> Shape {
+        self.layout.shape()
+    }
+
+    fn rules(&self) -> Rc<Rules> {
+        self.rules.clone()
+    }
+
+    fn num_cells(&self) -> usize {
+        self.layout.num_cells()
+    }
+
+    fn position(&self, index: usize) -> (i32, i32) {
+        self.layout.cell(index)
+    }
+
+    fn width(&self) -> i32 {
+        self.layout.width()
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> &mut Cell {
+        &mut self.cells[self.layout.index(x, y)]
+    }
+
+    fn cell(&self, x: i32, y: i32) -> &Cell {
+        &self.cells[self.layout.index(x, y)]
+    }
+
+    fn set_hint(&mut self, x: i32, y: i32, hint: i32) {
+        self.observer.highlight_cell(x, y, self.cell(x, y), true);
+        self.cell_mut(x, y).set_hint(hint);
+        self.observer.clear_cell(x, y, self.cell(x, y));
+    }
+
+    fn eliminate<F: FnMut((i32, i32))>(
+        &mut self,
+        x: i32,
+        y: i32,
+        digit: i32,
+        mark_solved: &mut F,
+    ) -> Result<usize, EliminationError> {
+        let layout = self.layout.clone();
+        let rules = self.rules.clone();
+        self.observer.highlight_cell(x, y, self.cell(x, y), true);
+        let mut eliminated = 0;
+        for (g, local_x, local_y) in layout.grids_at(x, y) {
+            let (ox, oy) = layout.origins()[g];
+            for &house in rules.houses_of(local_x, local_y) {
+                for &(peer_x, peer_y) in rules.house_cells(house) {
+                    if (peer_x, peer_y) != (local_x, local_y)
+                        && self.eliminate_in_cell(ox + peer_x, oy + peer_y, digit, mark_solved)?
+                    {
+                        eliminated += 1;
+                    }
+                }
+            }
+            for &(peer_x, peer_y) in rules.extra_peers(local_x, local_y) {
+                if self.eliminate_in_cell(ox + peer_x, oy + peer_y, digit, mark_solved)? {
+                    eliminated += 1;
+                }
+            }
+            for &(next_x, next_y) in rules.neighbours(local_x, local_y) {
+                for next in [digit - 1, digit + 1].iter().copied() {
+                    if (1..=layout.shape().size()).contains(&next)
+                        && self.eliminate_in_cell(ox + next_x, oy + next_y, next, mark_solved)?
+                    {
+                        eliminated += 1;
+                    }
+                }
+            }
+        }
+        self.observer.clear_cell(x, y, self.cell(x, y));
+        Ok(eliminated)
+    }
+
+    /// Layouts have no killer cages or constraints.
+    fn prune<F: FnMut((i32, i32))>(
+        &mut self,
+        _mark_solved: &mut F,
+    ) -> Result<usize, EliminationError> {
+        Ok(0)
+    }
+
+    fn eliminate_candidate(
+        &mut self,
+        x: i32,
+        y: i32,
+        digit: i32,
+    ) -> Result<bool, EliminationError> {
+        self.observer.highlight_cell(x, y, self.cell(x, y), true);
+        let eliminated = self.cell_mut(x, y).eliminate_possible(digit)?;
+        self.observer.clear_cell(x, y, self.cell(x, y));
+        Ok(eliminated)
+    }
+
+    fn invalidate(&self) {
+        for &(x, y) in &self.layout.cells {
+            self.observer.clear_cell(x, y, self.cell(x, y));
+        }
+    }
+
+    /// The digits of every covered cell in row-major order.
+    fn dump_solution(&self) -> Option<Vec<i32>> {
+        self.cells.iter().map(|cell| cell.solution()).collect()
+    }
+
+    /// Every grid on its own, the techniques do not look across grids.
+    fn sub_grids(&self) -> Vec<((i32, i32), Candidates)> {
+        (0..self.layout.origins().len())
+            .map(|g| {
+                let cells = self.layout.grid_values(g, &self.cells);
+                (
+                    self.layout.origins()[g],
+                    Candidates::from_cells(self.rules.clone(), cells),
+                )
+            })
+            .collect()
+    }
+}
@@ -1,12 +1,20 @@
 use crate::cell::Cell;
+use crate::house::{digit_char, Shape};
+use crate::multigrid::Layout;
+use crate::rules::Rules;
 use crate::solver::Guess;
+use crate::strategy::Deduction;
 
 use std::io;
 use std::io::Write;
 use std::{thread, time};
 use termion::{clear, color, cursor};
 
-const GRID_SIZE: i32 = 5;
+/// Screen columns and rows taken by a cell, which shows its candidates laid
+/// out like the cells of a block.
+fn cell_size(shape: Shape) -> (i32, i32) {
+    (shape.box_width + 2, shape.box_height + 2)
+}
 
 fn flush() {
     print!("{}", cursor::Hide);
@@ -69,6 +77,7 @@ pub trait GridObserver: Clone + std::fmt::Debug {
 
 pub trait SolverObserver {
     fn display_guesses(&mut self, _guesses: &[Guess]) {}
+    fn display_deduction(&mut self, _deduction: &Deduction) {}
 }
 
 #[derive(Clone, Debug)]
@@ -76,11 +85,66 @@ pub struct DummyGridObserver {}
 impl GridObserver for DummyGridObserver {}
 
 #[derive(Clone, Debug)]
-pub struct TermObserver {}
+pub struct TermObserver {
+    shape: Shape,
+}
+
 impl TermObserver {
     pub fn new() -> TermObserver {
+        TermObserver::with_shape(Shape::CLASSIC)
+    }
+
+    pub fn with_shape(shape: Shape) -> TermObserver {
+        TermObserver::with_rules(&Rules::new(shape, &[], &[]))
+    }
+
+    /// Draws the borders between the regions of a jigsaw sudoku, which
+    /// cannot be highlighted as rectangles. They use the last column and
+    /// row of each cell, which the highlights leave alone.
+    pub fn with_rules(rules: &Rules) -> TermObserver {
         print!("{}{}", clear::All, cursor::Hide);
-        TermObserver {}
+        let shape = rules.shape();
+        if rules.is_jigsaw() {
+            let (width, height) = cell_size(shape);
+            for i in 0..shape.num_cells() {
+                let (x, y) = shape.cell(i);
+                let (right, bottom) = (width * (x + 1) - 1, height * (y + 1) - 1);
+                if x + 1 < shape.size() && rules.block(x, y) != rules.block(x + 1, y) {
+                    for row in height * y + 1..=bottom {
+                        print!("{}|", cursor::Goto((right + 1) as u16, (row + 1) as u16));
+                    }
+                }
+                if y + 1 < shape.size() && rules.block(x, y) != rules.block(x, y + 1) {
+                    for column in width * x + 1..=right {
+                        print!(
+                            "{}-",
+                            cursor::Goto((column + 1) as u16, (bottom + 1) as u16)
+                        );
+                    }
+                }
+            }
+        }
+        TermObserver { shape }
+    }
+
+    fn draw_candidates(&self, cell_x: i32, cell_y: i32, cell: &Cell) {
+        let (width, height) = cell_size(self.shape);
+        for digit in 1..=self.shape.size() {
+            let character = if cell.is_possible(digit) {
+                digit_char(digit)
+            } else {
+                ' '
+            };
+            let x = (2 + width * cell_x + (digit - 1) % self.shape.box_width) as u16;
+            let y = (2 + height * cell_y + (digit - 1) / self.shape.box_width) as u16;
+            print!("{}{}", cursor::Goto(x, y), character);
+        }
+    }
+}
+
+impl Default for TermObserver {
+    fn default() -> TermObserver {
+        TermObserver::new()
     }
 }
 
@@ -92,10 +156,11 @@ impl Drop for TermObserver {
 
 impl GridObserver for TermObserver {
     fn highlight_block(&self, x: i32, y: i32) -> Highlight {
-        let x = x * GRID_SIZE;
-        let y = y * GRID_SIZE;
-        let width = GRID_SIZE * 3;
-        let height = GRID_SIZE * 3;
+        let (cell_width, cell_height) = cell_size(self.shape);
+        let x = x * cell_width;
+        let y = y * cell_height;
+        let width = cell_width * self.shape.box_width;
+        let height = cell_height * self.shape.box_height;
         draw_rectangle(x, y, width, height, "#");
         Highlight {
             x,
@@ -107,10 +172,11 @@ impl GridObserver for TermObserver {
     }
 
     fn highlight_row(&self, y: i32) -> Highlight {
+        let (cell_width, cell_height) = cell_size(self.shape);
         let x = 0;
-        let y = y * GRID_SIZE;
-        let width = GRID_SIZE * 9;
-        let height = GRID_SIZE;
+        let y = y * cell_height;
+        let width = cell_width * self.shape.size();
+        let height = cell_height;
         draw_rectangle(x, y, width, height, "#");
         Highlight {
             x,
@@ -122,10 +188,11 @@ impl GridObserver for TermObserver {
     }
 
     fn highlight_column(&self, x: i32) -> Highlight {
-        let x = x * GRID_SIZE;
+        let (cell_width, cell_height) = cell_size(self.shape);
+        let x = x * cell_width;
         let y = 0;
-        let width = GRID_SIZE;
-        let height = GRID_SIZE * 9;
+        let width = cell_width;
+        let height = cell_height * self.shape.size();
         draw_rectangle(x, y, width, height, "#");
         Highlight {
             x,
@@ -137,22 +204,13 @@ impl GridObserver for TermObserver {
     }
 
     fn highlight_cell(&self, cell_x: i32, cell_y: i32, cell: &Cell, selected: bool) {
-        for digit in 1..10 {
-            let character = if cell.is_possible(digit) {
-                digit.to_string()
-            } else {
-                " ".to_string()
-            };
-            if selected {
-                print!("{}", color::Fg(color::Magenta));
-            } else {
-                print!("{}", color::Fg(color::Blue));
-            }
-            let x = (2 + GRID_SIZE * cell_x + (digit - 1) % 3) as u16;
-            let y = (2 + GRID_SIZE * cell_y + (digit - 1) / 3) as u16;
-            print!("{}{}", cursor::Goto(x, y), character);
-            print!("{}", color::Fg(color::Black));
+        if selected {
+            print!("{}", color::Fg(color::Magenta));
+        } else {
+            print!("{}", color::Fg(color::Blue));
         }
+        self.draw_candidates(cell_x, cell_y, cell);
+        print!("{}", color::Fg(color::Black));
         flush();
         thread::sleep(time::Duration::from_millis(20));
     }
@@ -160,16 +218,7 @@ impl GridObserver for TermObserver {
         if cell.num_possibles() == 1 {
             print!("{}", color::Fg(color::Green));
         }
-        for digit in 1..10 {
-            let character = if cell.is_possible(digit) {
-                digit.to_string()
-            } else {
-                " ".to_string()
-            };
-            let x = (2 + GRID_SIZE * cell_x + (digit - 1) % 3) as u16;
-            let y = (2 + GRID_SIZE * cell_y + (digit - 1) / 3) as u16;
-            print!("{}{}", cursor::Goto(x, y), character)
-        }
+        self.draw_candidates(cell_x, cell_y, cell);
         print!("{}", color::Fg(color::Black));
     }
 }
@@ -178,30 +227,73 @@ pub struct DummySolverObserver {}
 impl SolverObserver for DummySolverObserver {}
 
 pub struct TermSolverObserver {
+    shape: Shape,
+    /// Columns and rows of cells drawn by `TermObserver`.
+    columns: i32,
+    rows: i32,
     prev_num_guesses: usize,
 }
 
 impl TermSolverObserver {
     pub fn new() -> TermSolverObserver {
+        TermSolverObserver::with_shape(Shape::CLASSIC)
+    }
+
+    pub fn with_shape(shape: Shape) -> TermSolverObserver {
+        TermSolverObserver {
+            shape,
+            columns: shape.size(),
+            rows: shape.size(),
+            prev_num_guesses: 0,
+        }
+    }
+
+    /// Makes room for the whole canvas of a layout.
+    pub fn with_layout(layout: &Layout) -> TermSolverObserver {
         TermSolverObserver {
+            shape: layout.shape(),
+            columns: layout.width(),
+            rows: layout.height(),
             prev_num_guesses: 0,
         }
     }
+
+    /// The screen size of the grid drawn by `TermObserver`.
+    fn grid_size(&self) -> (u16, u16) {
+        let (width, height) = cell_size(self.shape);
+        ((width * self.columns) as u16, (height * self.rows) as u16)
+    }
+}
+
+impl Default for TermSolverObserver {
+    fn default() -> TermSolverObserver {
+        TermSolverObserver::new()
+    }
 }
 
 impl SolverObserver for TermSolverObserver {
+    fn display_deduction(&mut self, deduction: &Deduction) {
+        print!(
+            "{}{}{}",
+            cursor::Goto(1, self.grid_size().1 + 2),
+            clear::CurrentLine,
+            deduction
+        );
+    }
+
     fn display_guesses(&mut self, guesses: &[Guess]) {
+        let left = self.grid_size().0 + 2;
         for i in guesses.len()..self.prev_num_guesses {
             print!(
                 "{}                                  ",
-                cursor::Goto(5 * 9 + 2, (i + 1) as u16),
+                cursor::Goto(left, (i + 1) as u16),
             );
         }
         self.prev_num_guesses = guesses.len();
         for (i, guess) in guesses.iter().enumerate() {
             print!(
                 "{} ({}, {}): {} [",
-                cursor::Goto(5 * 9 + 2, (i + 1) as u16),
+                cursor::Goto(left, (i + 1) as u16),
                 guess.x,
                 guess.y,
                 guess.digit,
@@ -0,0 +1,141 @@
+//! Difficulty ratings of puzzles, solving them with the weakest technique
+//! that makes progress at every step.
+//!
+//! The score is the difficulty of the hardest step on a scale similar to the
+//! Sudoku Explainer rating, from 1.5 for puzzles only needing hidden singles
+//! to 7.0 for alternating inference chains.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::reader::Puzzle;
+use crate::strategy::{next_deduction, Candidates, Deduction, PuzzleError, Technique};
+
+/// A coarse difficulty grouping of scores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bucket {
+    /// Singles only.
+    Easy,
+    /// Intersections, pairs and X-Wings.
+    Medium,
+    /// Triples, Swordfish, wings and rectangles.
+    Hard,
+    /// Quads, Jellyfish and BUG+1.
+    Fiendish,
+    /// Chains, or more than the techniques can solve.
+    Diabolical,
+}
+
+impl Bucket {
+    pub fn of(score: f32) -> Bucket {
+        if score < 2.5 {
+            Bucket::Easy
+        } else if score < 3.6 {
+            Bucket::Medium
+        } else if score < 5.0 {
+            Bucket::Hard
+        } else if score < 6.5 {
+            Bucket::Fiendish
+        } else {
+            Bucket::Diabolical
+        }
+    }
+}
+
+impl FromStr for Bucket {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Bucket, String> {
+        match name {
+            "easy" => Ok(Bucket::Easy),
+            "medium" => Ok(Bucket::Medium),
+            "hard" => Ok(Bucket::Hard),
+            "fiendish" => Ok(Bucket::Fiendish),
+            "diabolical" => Ok(Bucket::Diabolical),
+            _ => Err(format!(
+                "unknown difficulty '{}', expected one of easy, medium, hard, fiendish, diabolical",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Bucket::Easy => "easy",
+            Bucket::Medium => "medium",
+            Bucket::Hard => "hard",
+            Bucket::Fiendish => "fiendish",
+            Bucket::Diabolical => "diabolical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rating {
+    /// The difficulty of the hardest step.
+    pub score: f32,
+    pub bucket: Bucket,
+    /// The technique of the hardest step, if any were needed.
+    pub hardest: Option<Technique>,
+    /// Whether the techniques solved the puzzle. If not the score only
+    /// covers the steps up to where they got stuck.
+    pub solved: bool,
+    /// Every step, in order.
+    pub steps: Vec<Deduction>,
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1}", self.score)?;
+        if !self.solved {
+            write!(f, "+")?;
+        }
+        write!(f, " {}", self.bucket)?;
+        if let Some(technique) = self.hardest {
+            write!(f, " ({})", technique)?;
+        }
+        if !self.solved {
+            write!(f, ", needs guessing")?;
+        }
+        Ok(())
+    }
+}
+
+/// Rates `puzzle`, failing if it turns out to have no solution. Techniques
+/// that assume a single solution are only used with `assume_unique`.
+/// Puzzles with a layout are not supported and fail too.
+pub fn rate(puzzle: &Puzzle, assume_unique: bool) -> Result<Rating, PuzzleError> {
+    let mut candidates = Candidates::from_puzzle(puzzle)?;
+    let mut steps: Vec<Deduction> = Vec::new();
+    while !candidates.is_solved() {
+        // Techniques are ordered by difficulty, so the first one found is
+        // the weakest.
+        match next_deduction(&candidates, Technique::ALL, assume_unique) {
+            Some(deduction) => {
+                candidates.apply(&deduction)?;
+                steps.push(deduction);
+            }
+            None => break,
+        }
+    }
+    let hardest = steps
+        .iter()
+        .map(|step| step.technique)
+        .max_by(|a, b| a.difficulty().partial_cmp(&b.difficulty()).unwrap());
+    let score = hardest.map_or(0.0, |technique| technique.difficulty());
+    let solved = candidates.is_solved();
+    Ok(Rating {
+        score,
+        bucket: if solved {
+            Bucket::of(score)
+        } else {
+            Bucket::Diabolical
+        },
+        hardest,
+        solved,
+        steps,
+    })
+}
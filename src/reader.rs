@@ -1,7 +1,16 @@
-use std::fs::File;
-use std::io::prelude::*;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
 
+use crate::constraint::{self, Constraint};
+use crate::house::{cell_name, char_digit, digit_char, parse_cell_name, Shape};
+use crate::multigrid::Layout;
+use crate::rules::{Cage, Rules, Variant};
 use crate::solver::Solver;
+use crate::validate::{self, Conflict};
+use serde_json::Value;
 
 #[allow(dead_code)] // Useful as hardcoded example
 pub fn read_hardcoded(solver: &mut dyn Solver) {
@@ -45,23 +54,1171 @@ pub fn read_hardcoded(solver: &mut dyn Solver) {
     solver.set_hint(8, 8, 9);
 }
 
-pub fn read(filename: &str, solver: &mut dyn Solver, offset: usize) -> std::io::Result<()> {
-    let mut file = File::open(filename)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    match contents.lines().skip(offset).next() {
-        Some(first) => {
-            for (i, c) in first.chars().enumerate() {
-                if let Some(digit) = c.to_digit(10) {
-                    let x = (i % 9) as i32;
-                    let y = (i / 9) as i32;
-                    solver.set_hint(x, y, digit as i32);
+/// Text formats puzzles can be read from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Detect the format from the first non-empty line.
+    Auto,
+    /// One puzzle per line, 81 characters with `.` for blanks. Other sizes
+    /// are told apart by the length of the line, 16 characters for 4x4
+    /// grids, 256 for 16x16 grids and so on.
+    Line,
+    /// One puzzle per line, 81 characters with `0` (or `.`) for blanks.
+    Zeros,
+    /// Nine rows of nine cells, separators such as `|`, `-` and `+` are
+    /// ignored. Blanks are written as `.`, `0`, `_` or `*`. The first row
+    /// sets the size of the grid.
+    Grid,
+    /// SadMan Software `.sdk`: `#` metadata lines and `[...]` section headers
+    /// followed by nine rows of nine cells with `.` for blanks.
+    Sdk,
+    /// SadMan Software Simple Sudoku `.ss`: nine rows written as `...|...|...`
+    /// with `-----------` separator lines.
+    Ss,
+    /// JSON arrays, either 81 numbers or nine arrays of nine numbers, with `0`
+    /// or `null` for blanks. An array of such arrays holds several puzzles.
+    Json,
+}
+
+impl Format {
+    /// Format implied by the extension of `filename`, `Auto` if unknown.
+    pub fn from_extension(filename: &str) -> Format {
+        match Path::new(filename).extension().and_then(|e| e.to_str()) {
+            Some("sdk") => Format::Sdk,
+            Some("ss") => Format::Ss,
+            Some("json") => Format::Json,
+            _ => Format::Auto,
+        }
+    }
+
+    fn detect(line: &str) -> Format {
+        if line.starts_with('#') {
+            Format::Sdk
+        } else if let Some(rest) = line.strip_prefix('[') {
+            if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                Format::Sdk
+            } else {
+                Format::Json
+            }
+        } else if line.chars().count() >= 81 || is_small_line(line) {
+            if line.contains('0') {
+                Format::Zeros
+            } else {
+                Format::Line
+            }
+        } else if line.contains('|') && !line.contains(' ') {
+            Format::Ss
+        } else {
+            Format::Grid
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name {
+            "auto" => Ok(Format::Auto),
+            "line" => Ok(Format::Line),
+            "zeros" => Ok(Format::Zeros),
+            "grid" => Ok(Format::Grid),
+            "sdk" => Ok(Format::Sdk),
+            "ss" => Ok(Format::Ss),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown format '{}', expected one of auto, line, zeros, grid, sdk, ss, json",
+                name
+            )),
+        }
+    }
+}
+
+/// Why a puzzle could not be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseErrorReason {
+    /// A line or row has the wrong number of cells.
+    WrongLength { expected: usize, found: usize },
+    /// A character that is neither a blank nor a digit of the grid.
+    InvalidChar(char),
+    /// `0` is not a digit, blanks are written as `.` in this format.
+    ZeroDigit,
+    /// The input ended before all rows of a puzzle were read.
+    Incomplete { rows: usize, expected: usize },
+    /// Malformed JSON or a JSON value that does not describe a puzzle.
+    Json(String),
+    /// A variant declaration naming an unknown variant.
+    Variant(String),
+    /// A malformed cage declaration, or cages that overlap or leave the grid.
+    Cage(String),
+    /// A region map that does not split the grid into regions the size of
+    /// a row.
+    Regions(String),
+    /// Malformed JSON declaring constraints, or constraints that do not fit
+    /// the grid.
+    Constraint(String),
+    /// An unknown layout, or grids that do not fit it or disagree on the
+    /// cells they share.
+    Layout(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub reason: ParseErrorReason,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.reason {
+            ParseErrorReason::WrongLength { expected, found } => {
+                write!(f, "expected {} cells, found {}", expected, found)
+            }
+            ParseErrorReason::InvalidChar(c) => write!(f, "invalid character '{}'", c),
+            ParseErrorReason::ZeroDigit => write!(f, "'0' is not a digit, use '.' for blanks"),
+            ParseErrorReason::Incomplete { rows, expected } => {
+                write!(f, "puzzle ends after {} of {} rows", rows, expected)
+            }
+            ParseErrorReason::Json(message)
+            | ParseErrorReason::Variant(message)
+            | ParseErrorReason::Cage(message)
+            | ParseErrorReason::Regions(message)
+            | ParseErrorReason::Constraint(message)
+            | ParseErrorReason::Layout(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(err) => err.fmt(f),
+            ReadError::Parse(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> ReadError {
+        ReadError::Io(err)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(err: ParseError) -> ReadError {
+        ReadError::Parse(err)
+    }
+}
+
+/// A parsed puzzle, hints in row-major order with `None` for blanks.
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub shape: Shape,
+    /// Rules on top of the rows, columns and blocks, none for classic sudoku.
+    pub variants: Vec<Variant>,
+    /// The cages of a killer sudoku.
+    pub cages: Vec<Cage>,
+    /// The region of each cell of a jigsaw sudoku in row-major order,
+    /// numbered from 0, instead of the rectangular blocks.
+    pub regions: Option<Vec<i32>>,
+    /// Thermometers, arrows and the other constraints of arithmetic
+    /// variants.
+    pub constraints: Vec<Rc<dyn Constraint>>,
+    /// Where the grids of a puzzle made of several overlapping ones sit,
+    /// each of them of `shape`. The hints are then those of the cells the
+    /// grids cover in row-major order.
+    pub layout: Option<Layout>,
+    pub hints: Vec<Option<i32>>,
+}
+
+impl Puzzle {
+    /// The houses and peers of the grid, or of each grid of a layout.
+    pub fn rules(&self) -> Rules {
+        let rules = match &self.regions {
+            Some(regions) => Rules::jigsaw(self.shape, regions, &self.variants, &self.cages),
+            None => Rules::new(self.shape, &self.variants, &self.cages),
+        };
+        rules.with_constraints(&self.constraints)
+    }
+
+    /// Pairs of hints that contradict each other.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let layout = match &self.layout {
+            Some(layout) => layout,
+            None => return validate::find_conflicts(&self.rules(), &self.hints),
+        };
+        let rules = self.rules();
+        let mut conflicts: Vec<Conflict> = Vec::new();
+        for (g, &(left, top)) in layout.origins().iter().enumerate() {
+            let hints = layout.grid_values(g, &self.hints);
+            for mut conflict in validate::find_conflicts(&rules, &hints) {
+                conflict.first = (left + conflict.first.0, top + conflict.first.1);
+                conflict.second = (left + conflict.second.0, top + conflict.second.1);
+                // Grids sharing both cells find the same conflict.
+                if !conflicts
+                    .iter()
+                    .any(|other| (other.first, other.second) == (conflict.first, conflict.second))
+                {
+                    conflicts.push(conflict);
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// The position of the cell of hint `i`.
+    pub fn cell(&self, i: usize) -> (i32, i32) {
+        match &self.layout {
+            Some(layout) => layout.cell(i),
+            None => self.shape.cell(i),
+        }
+    }
+
+    /// The lines declaring the rules of the puzzle, which [`read`] needs
+    /// before the grid to read it back as the same puzzle. There are none
+    /// for a classic sudoku.
+    pub fn declarations(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !self.variants.is_empty() {
+            let names: Vec<&str> = self.variants.iter().map(|variant| variant.name()).collect();
+            lines.push(format!("variant: {}", names.join(", ")));
+        }
+        if let Some(layout) = &self.layout {
+            let origins: Vec<String> = layout
+                .origins()
+                .iter()
+                .map(|&(x, y)| format!("{},{}", x, y))
+                .collect();
+            lines.push(format!("layout: {}", origins.join(" ")));
+        }
+        if let Some(regions) = &self.regions {
+            let map: String = regions
+                .iter()
+                .map(|&region| digit_char(region + 1))
+                .collect();
+            lines.push(format!("regions: {}", map));
+        }
+        for cage in &self.cages {
+            let cells: Vec<String> = cage.cells.iter().map(|&(x, y)| cell_name(x, y)).collect();
+            lines.push(format!("cage: {} {}", cage.sum, cells.join(" ")));
+        }
+        if !self.constraints.is_empty() {
+            let objects: Vec<Value> = self.constraints.iter().map(|c| c.to_json()).collect();
+            lines.push(format!("constraints: {}", Value::Array(objects)));
+        }
+        lines
+    }
+
+    pub fn apply(&self, solver: &mut dyn Solver) {
+        for (i, hint) in self.hints.iter().enumerate() {
+            if let Some(digit) = hint {
+                let (x, y) = self.cell(i);
+                solver.set_hint(x, y, *digit);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Puzzle {
+    /// Writes the declarations of the puzzle, each on its own line, followed
+    /// by its hints in the line format, so that [`read`] reads it back as
+    /// the same puzzle. A classic sudoku is the single line `from_str`
+    /// parses. The grids of a layout are written one after the other,
+    /// separated by spaces. Variants and layouts hold until declared again,
+    /// so they are reset after the grid for the puzzles that follow.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.declarations() {
+            writeln!(f, "{}", line)?;
+        }
+        let grids = match &self.layout {
+            Some(layout) => (0..layout.origins().len())
+                .map(|g| layout.grid_values(g, &self.hints))
+                .collect(),
+            None => vec![self.hints.clone()],
+        };
+        for (g, hints) in grids.iter().enumerate() {
+            if g > 0 {
+                write!(f, " ")?;
+            }
+            for hint in hints {
+                match hint {
+                    Some(digit) => write!(f, "{}", digit_char(*digit))?,
+                    None => write!(f, ".")?,
+                }
+            }
+        }
+        if !self.variants.is_empty() {
+            write!(f, "\nvariant: none")?;
+        }
+        if self.layout.is_some() {
+            write!(f, "\nlayout: none")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Puzzle {
+    type Err = ParseError;
+
+    /// Parses a single line with `.` for blanks, 81 characters for the
+    /// classic grid.
+    fn from_str(line: &str) -> Result<Puzzle, ParseError> {
+        let shape = line_shape(line);
+        parse_cells(line, 1, Format::Line, shape, shape.num_cells()).map(|hints| Puzzle {
+            shape,
+            variants: Vec::new(),
+            cages: Vec::new(),
+            regions: None,
+            constraints: Vec::new(),
+            layout: None,
+            hints,
+        })
+    }
+}
+
+/// The shape of a grid written on one line, the classic one if the length
+/// does not fit any.
+fn line_shape(line: &str) -> Shape {
+    Shape::for_cells(line.chars().count()).unwrap_or(Shape::CLASSIC)
+}
+
+/// Whether `line` is a whole grid smaller than the classic one rather than
+/// a row of a larger grid, which can have as many cells. Only the digits of
+/// the smaller grid may appear.
+fn is_small_line(line: &str) -> bool {
+    let size = match Shape::for_cells(line.chars().count()) {
+        Some(shape) if shape.size() < 9 => shape.size(),
+        _ => return false,
+    };
+    line.chars()
+        .all(|c| c == '.' || c == '0' || char_digit(c).is_some_and(|digit| digit <= size))
+}
+
+/// The shape of a grid written in rows, from the number of cells in its
+/// first row.
+fn row_shape(line: &str, format: Format) -> Shape {
+    let cells = line
+        .chars()
+        .filter(|&c| match format {
+            Format::Grid => !"|+- \t".contains(c),
+            Format::Ss => c != '|',
+            _ => true,
+        })
+        .count();
+    Shape::for_size(cells as i32).unwrap_or(Shape::CLASSIC)
+}
+
+/// Parses the cells of `line`, which must hold exactly `expected` of them.
+/// Separator characters are skipped in the formats that allow them.
+fn parse_cells(
+    line: &str,
+    line_number: usize,
+    format: Format,
+    shape: Shape,
+    expected: usize,
+) -> Result<Vec<Option<i32>>, ParseError> {
+    let error = |column, reason| ParseError {
+        line: line_number,
+        column,
+        reason,
+    };
+    let mut cells = Vec::with_capacity(expected);
+    for (i, c) in line.chars().enumerate() {
+        let block_edge =
+            (1..expected).contains(&cells.len()) && cells.len() as i32 % shape.box_width == 0;
+        let cell = match (c, format) {
+            ('.', _) => None,
+            ('0', Format::Zeros) | ('0', Format::Grid) => None,
+            ('_', Format::Grid) | ('*', Format::Grid) => None,
+            ('|', Format::Grid) | ('+', Format::Grid) | ('-', Format::Grid) => continue,
+            (' ', Format::Grid) | ('\t', Format::Grid) => continue,
+            ('|', Format::Ss) if block_edge => continue,
+            ('0', _) => return Err(error(i + 1, ParseErrorReason::ZeroDigit)),
+            _ => match char_digit(c) {
+                Some(digit) if digit <= shape.size() => Some(digit),
+                _ => return Err(error(i + 1, ParseErrorReason::InvalidChar(c))),
+            },
+        };
+        cells.push(cell);
+    }
+    if cells.len() != expected {
+        return Err(error(
+            line.chars().count() + 1,
+            ParseErrorReason::WrongLength {
+                expected,
+                found: cells.len(),
+            },
+        ));
+    }
+    Ok(cells)
+}
+
+/// Whether `line` holds no cells in a row based format, like the separator
+/// lines of the grid formats and the metadata of `.sdk` files.
+fn is_skipped_row(line: &str, format: Format) -> bool {
+    match format {
+        Format::Grid => line.chars().all(|c| "|+-= \t".contains(c)),
+        Format::Sdk => line.starts_with('#') || line.starts_with('['),
+        Format::Ss => line.chars().all(|c| c == '-' || c == '+'),
+        _ => false,
+    }
+}
+
+fn json_error(text: &str, offset: usize, message: String) -> ParseError {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    ParseError {
+        line,
+        column,
+        reason: ParseErrorReason::Json(message),
+    }
+}
+
+fn json_cell(value: &Value, size: i32) -> Result<Option<i32>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Number(n) => match n.as_u64() {
+            Some(0) => Ok(None),
+            Some(digit) if digit <= size as u64 => Ok(Some(digit as i32)),
+            _ => Err(format!("{} is not a digit", n)),
+        },
+        _ => Err(format!("expected a digit or null, found {}", value)),
+    }
+}
+
+fn json_is_row(value: &Value, size: usize) -> bool {
+    matches!(value, Value::Array(row) if row.len() == size && !row.iter().any(|v| v.is_array()))
+}
+
+/// Collects the puzzles described by a JSON value: a flat array of 81 cells,
+/// nine arrays of nine cells, or an array of puzzles. Other sizes work the
+/// same, 16 cells or four rows of four for 4x4 grids.
+fn json_puzzles(value: &Value, puzzles: &mut Vec<Result<Puzzle, String>>) {
+    let items = match value {
+        Value::Array(items) => items,
+        _ => {
+            puzzles.push(Err(format!("expected an array, found {}", value)));
+            return;
+        }
+    };
+    let flat_shape = Shape::for_cells(items.len());
+    let row_shape = Shape::for_size(items.len() as i32);
+    let (shape, cells): (Shape, Vec<&Value>) =
+        if let (Some(shape), false) = (flat_shape, items.iter().any(|v| v.is_array())) {
+            (shape, items.iter().collect())
+        } else if let (Some(shape), true) = (
+            row_shape,
+            items.iter().all(|row| json_is_row(row, items.len())),
+        ) {
+            (
+                shape,
+                items
+                    .iter()
+                    .flat_map(|row| row.as_array().unwrap().iter())
+                    .collect(),
+            )
+        } else if !items.is_empty() && items.iter().all(|v| v.is_array()) {
+            for item in items {
+                json_puzzles(item, puzzles);
+            }
+            return;
+        } else {
+            puzzles.push(Err(format!(
+                "expected 81 cells or 9 rows of 9 cells, found an array of {}",
+                items.len()
+            )));
+            return;
+        };
+    puzzles.push(
+        cells
+            .into_iter()
+            .map(|cell| json_cell(cell, shape.size()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|hints| Puzzle {
+                shape,
+                variants: Vec::new(),
+                cages: Vec::new(),
+                regions: None,
+                constraints: Vec::new(),
+                layout: None,
+                hints,
+            }),
+    );
+}
+
+/// serde_json appends the position to its messages, which `ParseError` reports
+/// separately.
+fn strip_json_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// Parses all JSON values in `text`, reporting positions relative to
+/// `first_line`.
+fn parse_json(text: &str, first_line: usize) -> Vec<Result<Puzzle, ParseError>> {
+    let mut results = Vec::new();
+    let mut values = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+    loop {
+        let start = values.byte_offset();
+        let start = start + (text[start..].len() - text[start..].trim_start().len());
+        match values.next() {
+            Some(Ok(value)) => {
+                let mut puzzles = Vec::new();
+                json_puzzles(&value, &mut puzzles);
+                results.extend(
+                    puzzles
+                        .into_iter()
+                        .map(|puzzle| puzzle.map_err(|message| json_error(text, start, message))),
+                );
+            }
+            Some(Err(err)) => {
+                results.push(Err(ParseError {
+                    line: err.line(),
+                    column: err.column(),
+                    reason: ParseErrorReason::Json(strip_json_position(&err.to_string())),
+                }));
+                break;
+            }
+            None => break,
+        }
+    }
+    for result in results.iter_mut() {
+        if let Err(err) = result {
+            err.line += first_line - 1;
+        }
+    }
+    results
+}
+
+/// Iterator over the puzzles in a stream. Empty lines are skipped. A line
+/// such as `variant: diagonal, anti-knight` between puzzles declares the
+/// variants of the puzzles after it, `variant: none` goes back to classic
+/// sudoku. Lines such as `cage: 15 r1c1 r1c2 r2c1` before a puzzle give it
+/// killer cages, the sum followed by the cells. A line such as
+/// `regions: 111222333...` before a puzzle makes it a jigsaw sudoku, with a
+/// label for the region of every cell in row-major order. Lines such as
+/// `constraints: {"type": "thermo", "cells": ["r1c1", "r1c2"]}` before a
+/// puzzle give it the constraints described by a JSON object or an array of
+/// them, see [`constraint::from_json`]. A line such as `layout: samurai`
+/// makes the puzzles after it, until `layout: none`, made of several
+/// overlapping grids, each written in the line format and separated by
+/// whitespace or line breaks.
+pub struct Puzzles<R: BufRead> {
+    input: R,
+    format: Format,
+    line: String,
+    line_number: usize,
+    json: Option<std::vec::IntoIter<Result<Puzzle, ParseError>>>,
+    /// The shape of the grid being read in a row based format, set by its
+    /// first row.
+    shape: Option<Shape>,
+    /// The variants of the puzzles read, until the next declaration.
+    variants: Vec<Variant>,
+    /// The cages declared for the next puzzle, with the lines declaring them.
+    cages: Vec<(usize, Cage)>,
+    /// The region map declared for the next puzzle and the line declaring it.
+    regions: Option<(usize, String)>,
+    /// The constraints declared for the next puzzle, with the lines declaring
+    /// them.
+    constraints: Vec<(usize, Value)>,
+    /// Where the grids of the puzzles read sit, until the next declaration,
+    /// and the line declaring them.
+    origins: Option<(usize, Vec<(i32, i32)>)>,
+}
+
+impl<R: BufRead> Puzzles<R> {
+    pub fn new(input: R, format: Format) -> Puzzles<R> {
+        Puzzles {
+            input,
+            format,
+            line: String::new(),
+            line_number: 0,
+            json: None,
+            shape: None,
+            variants: Vec::new(),
+            cages: Vec::new(),
+            regions: None,
+            constraints: Vec::new(),
+            origins: None,
+        }
+    }
+
+    /// The variants of the puzzles before the first declaration.
+    pub fn variants(mut self, variants: &[Variant]) -> Self {
+        self.variants = variants.to_vec();
+        self
+    }
+
+    /// Adds the declared variants, cages and constraints to a puzzle just
+    /// read.
+    fn declare(&mut self, puzzle: Puzzle) -> Result<Puzzle, ParseError> {
+        if puzzle.layout.is_some()
+            && (!self.cages.is_empty() || self.regions.is_some() || !self.constraints.is_empty())
+        {
+            return Err(ParseError {
+                line: self.line_number,
+                column: 1,
+                reason: ParseErrorReason::Layout(
+                    "cages, regions and constraints are not supported with a layout".to_string(),
+                ),
+            });
+        }
+        let size = puzzle.shape.size();
+        let mut cages = Vec::new();
+        for (line, cage) in std::mem::take(&mut self.cages) {
+            if let Some(&(x, y)) = cage.cells.iter().find(|&&(x, y)| x >= size || y >= size) {
+                return Err(ParseError {
+                    line,
+                    column: 1,
+                    reason: ParseErrorReason::Cage(format!(
+                        "{} is outside the {} grid",
+                        cell_name(x, y),
+                        puzzle.shape
+                    )),
+                });
+            }
+            cages.push(cage);
+        }
+        let regions =
+            match self.regions.take() {
+                Some((line, map)) => Some(parse_regions(&map, puzzle.shape).map_err(
+                    |message| ParseError {
+                        line,
+                        column: 1,
+                        reason: ParseErrorReason::Regions(message),
+                    },
+                )?),
+                None => None,
+            };
+        let mut constraints = Vec::new();
+        for (line, value) in std::mem::take(&mut self.constraints) {
+            let declared =
+                constraint::from_json(&value, puzzle.shape).map_err(|message| ParseError {
+                    line,
+                    column: 1,
+                    reason: ParseErrorReason::Constraint(message),
+                })?;
+            constraints.extend(declared);
+        }
+        Ok(Puzzle {
+            variants: self.variants.clone(),
+            cages,
+            regions,
+            constraints,
+            ..puzzle
+        })
+    }
+
+    fn puzzle(&mut self, shape: Shape, hints: Vec<Option<i32>>) -> Result<Puzzle, ParseError> {
+        self.declare(Puzzle {
+            shape,
+            variants: Vec::new(),
+            cages: Vec::new(),
+            regions: None,
+            constraints: Vec::new(),
+            layout: None,
+            hints,
+        })
+    }
+
+    /// Puts the grids read one after the other on the canvas of the layout
+    /// declared at line `declared`.
+    fn layout_puzzle(
+        &mut self,
+        shape: Shape,
+        declared: usize,
+        origins: &[(i32, i32)],
+        grids: &[Option<i32>],
+    ) -> Result<Puzzle, ParseError> {
+        let error = |line, message| ParseError {
+            line,
+            column: 1,
+            reason: ParseErrorReason::Layout(message),
+        };
+        let layout = Layout::new(shape, origins).map_err(|message| error(declared, message))?;
+        let mut hints = vec![None; layout.num_cells()];
+        for (g, grid) in grids.chunks(shape.num_cells()).enumerate() {
+            let (left, top) = origins[g];
+            for (i, &hint) in grid.iter().enumerate() {
+                let (x, y) = shape.cell(i);
+                let (x, y) = (left + x, top + y);
+                let cell = &mut hints[layout.index(x, y)];
+                match (*cell, hint) {
+                    (Some(first), Some(second)) if first != second => {
+                        return Err(error(
+                            self.line_number,
+                            format!(
+                                "{} is {} in one grid but {} in grid {}",
+                                cell_name(x, y),
+                                first,
+                                second,
+                                g + 1
+                            ),
+                        ))
+                    }
+                    (_, Some(digit)) => *cell = Some(digit),
+                    _ => {}
                 }
             }
         }
-        None => {
-            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+        self.declare(Puzzle {
+            shape,
+            variants: Vec::new(),
+            cages: Vec::new(),
+            regions: None,
+            constraints: Vec::new(),
+            layout: Some(layout),
+            hints,
+        })
+    }
+
+    /// Adds a cage for the next puzzle, failing on cells already in one.
+    fn add_cage(&mut self, cage: Cage) -> Result<(), String> {
+        for cell in &cage.cells {
+            if self
+                .cages
+                .iter()
+                .any(|(_, other)| other.cells.contains(cell))
+            {
+                return Err(format!(
+                    "{} is already in a cage",
+                    cell_name(cell.0, cell.1)
+                ));
+            }
         }
+        self.cages.push((self.line_number, cage));
+        Ok(())
+    }
+
+    /// JSON values may span lines, so the rest of the stream is read at once.
+    fn read_json(&mut self) -> Result<(), ReadError> {
+        let first_line = self.line_number;
+        let mut text = std::mem::take(&mut self.line);
+        self.input.read_to_string(&mut text)?;
+        self.json = Some(parse_json(&text, first_line).into_iter());
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for Puzzles<R> {
+    type Item = Result<Puzzle, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(json) = &mut self.json {
+            return json.next().map(|puzzle| {
+                puzzle
+                    .and_then(|puzzle| self.declare(puzzle))
+                    .map_err(From::from)
+            });
+        }
+        let mut hints = Vec::with_capacity(81);
+        self.shape = None;
+        loop {
+            self.line.clear();
+            match self.input.read_line(&mut self.line) {
+                Ok(0) if hints.is_empty() => return None,
+                Ok(0) if self.origins.is_some() => {
+                    let grid_cells = self.shape.unwrap_or_default().num_cells();
+                    return Some(Err(ParseError {
+                        line: self.line_number + 1,
+                        column: 1,
+                        reason: ParseErrorReason::Layout(format!(
+                            "puzzle ends after {} of {} grids",
+                            hints.len() / grid_cells,
+                            self.origins
+                                .as_ref()
+                                .map_or(0, |(_, origins)| origins.len())
+                        )),
+                    }
+                    .into()));
+                }
+                Ok(0) => {
+                    let size = self.shape.unwrap_or_default().size() as usize;
+                    return Some(Err(ParseError {
+                        line: self.line_number + 1,
+                        column: 1,
+                        reason: ParseErrorReason::Incomplete {
+                            rows: hints.len() / size,
+                            expected: size,
+                        },
+                    }
+                    .into()));
+                }
+                Ok(_) => self.line_number += 1,
+                Err(err) => return Some(Err(err.into())),
+            }
+            let line = self.line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if hints.is_empty() {
+                if let Some(list) = line.strip_prefix("variant:") {
+                    match Variant::parse_list(list) {
+                        Ok(variants) => self.variants = variants,
+                        Err(message) => {
+                            return Some(Err(ParseError {
+                                line: self.line_number,
+                                column: 1,
+                                reason: ParseErrorReason::Variant(message),
+                            }
+                            .into()))
+                        }
+                    }
+                    continue;
+                }
+                if let Some(declaration) = line.strip_prefix("cage:") {
+                    let cage = parse_cage(declaration);
+                    if let Err(message) = cage.and_then(|cage| self.add_cage(cage)) {
+                        return Some(Err(ParseError {
+                            line: self.line_number,
+                            column: 1,
+                            reason: ParseErrorReason::Cage(message),
+                        }
+                        .into()));
+                    }
+                    continue;
+                }
+                if let Some(map) = line.strip_prefix("regions:") {
+                    self.regions = Some((self.line_number, map.to_string()));
+                    continue;
+                }
+                if let Some(json) = line.strip_prefix("constraints:") {
+                    match serde_json::from_str(json) {
+                        Ok(value) => self.constraints.push((self.line_number, value)),
+                        Err(err) => {
+                            return Some(Err(ParseError {
+                                line: self.line_number,
+                                column: "constraints:".len() + err.column(),
+                                reason: ParseErrorReason::Constraint(strip_json_position(
+                                    &err.to_string(),
+                                )),
+                            }
+                            .into()))
+                        }
+                    }
+                    continue;
+                }
+                if let Some(name) = line.strip_prefix("layout:") {
+                    let origins = match name.trim() {
+                        "none" => Ok(None),
+                        name => Layout::parse_origins(name)
+                            .map(|origins| Some((self.line_number, origins))),
+                    };
+                    match origins {
+                        Ok(origins) => self.origins = origins,
+                        Err(message) => {
+                            return Some(Err(ParseError {
+                                line: self.line_number,
+                                column: 1,
+                                reason: ParseErrorReason::Layout(message),
+                            }
+                            .into()))
+                        }
+                    }
+                    continue;
+                }
+            }
+            if let Some((declared, origins)) = self.origins.clone() {
+                // The grids of a layout are read like lines, '0' or '.' for
+                // blanks, as many to a line as there are.
+                let line = self.line.trim_end();
+                for word in line.split_whitespace() {
+                    let column = word.as_ptr() as usize - line.as_ptr() as usize;
+                    let shape = *self.shape.get_or_insert_with(|| line_shape(word));
+                    if hints.len() == origins.len() * shape.num_cells() {
+                        return Some(Err(ParseError {
+                            line: self.line_number,
+                            column: column + 1,
+                            reason: ParseErrorReason::Layout(format!(
+                                "the layout has only {} grids",
+                                origins.len()
+                            )),
+                        }
+                        .into()));
+                    }
+                    match parse_cells(
+                        word,
+                        self.line_number,
+                        Format::Zeros,
+                        shape,
+                        shape.num_cells(),
+                    ) {
+                        Ok(grid) => hints.extend(grid),
+                        Err(err) => {
+                            return Some(Err(ParseError {
+                                column: err.column + column,
+                                ..err
+                            }
+                            .into()))
+                        }
+                    }
+                }
+                let shape = self.shape.unwrap_or_default();
+                if hints.len() == origins.len() * shape.num_cells() {
+                    return Some(
+                        self.layout_puzzle(shape, declared, &origins, &hints)
+                            .map_err(From::from),
+                    );
+                }
+                continue;
+            }
+            if self.format == Format::Auto {
+                self.format = Format::detect(line);
+            }
+            match self.format {
+                Format::Line | Format::Zeros => {
+                    let shape = line_shape(line);
+                    return Some(
+                        parse_cells(
+                            line,
+                            self.line_number,
+                            self.format,
+                            shape,
+                            shape.num_cells(),
+                        )
+                        .and_then(|hints| self.puzzle(shape, hints))
+                        .map_err(From::from),
+                    );
+                }
+                Format::Json => {
+                    if let Err(err) = self.read_json() {
+                        return Some(Err(err));
+                    }
+                    return self.next();
+                }
+                _ => {}
+            }
+            if is_skipped_row(line, self.format) {
+                continue;
+            }
+            let format = self.format;
+            let shape = *self.shape.get_or_insert_with(|| row_shape(line, format));
+            let size = shape.size() as usize;
+            match parse_cells(line, self.line_number, self.format, shape, size) {
+                Ok(row) => hints.extend(row),
+                Err(err) => return Some(Err(err.into())),
+            }
+            if hints.len() == shape.num_cells() {
+                return Some(self.puzzle(shape, hints).map_err(From::from));
+            }
+        }
+    }
+}
+
+/// Parses the sum and cells of a cage, such as `15 r1c1 r1c2 r2c1`.
+fn parse_cage(declaration: &str) -> Result<Cage, String> {
+    let mut words = declaration
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty());
+    let sum = match words.next().map(str::parse::<i32>) {
+        Some(Ok(sum)) if sum > 0 => sum,
+        _ => return Err("expected the sum of the cage before its cells".to_string()),
+    };
+    let mut cells = Vec::new();
+    for word in words {
+        let cell = parse_cell_name(word)
+            .ok_or_else(|| format!("invalid cell '{}', expected a name like r1c1", word))?;
+        if cells.contains(&cell) {
+            return Err(format!("{} appears twice in the cage", word));
+        }
+        cells.push(cell);
+    }
+    if cells.is_empty() {
+        return Err(format!("the cage of {} has no cells", sum));
+    }
+    Ok(Cage { sum, cells })
+}
+
+/// Numbers the regions of a map such as `111222333...` in order of first
+/// appearance. Whitespace is ignored, any other character labels a region.
+fn parse_regions(map: &str, shape: Shape) -> Result<Vec<i32>, String> {
+    let size = shape.size() as usize;
+    let mut labels: Vec<char> = Vec::new();
+    let mut counts: Vec<usize> = Vec::new();
+    let mut regions = Vec::with_capacity(shape.num_cells());
+    for c in map.chars().filter(|c| !c.is_whitespace()) {
+        let region = match labels.iter().position(|&label| label == c) {
+            Some(region) => region,
+            None => {
+                labels.push(c);
+                counts.push(0);
+                labels.len() - 1
+            }
+        };
+        counts[region] += 1;
+        regions.push(region as i32);
+    }
+    if regions.len() != shape.num_cells() {
+        return Err(format!(
+            "expected a region for each of the {} cells, found {}",
+            shape.num_cells(),
+            regions.len()
+        ));
+    }
+    if labels.len() != size {
+        return Err(format!("expected {} regions, found {}", size, labels.len()));
+    }
+    if let Some(region) = counts.iter().position(|&count| count != size) {
+        return Err(format!(
+            "region '{}' has {} cells, expected {}",
+            labels[region], counts[region], size
+        ));
+    }
+    Ok(regions)
+}
+
+pub fn read<R: BufRead>(input: R, format: Format) -> Puzzles<R> {
+    Puzzles::new(input, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMPTY: &str =
+        ".................................................................................";
+
+    /// The error reading the first puzzle of `input`.
+    fn parse_error(input: &str, format: Format) -> ParseError {
+        match read(input.as_bytes(), format).next() {
+            Some(Err(ReadError::Parse(err))) => err,
+            other => panic!(
+                "expected a parse error, found {:?}",
+                other.map(|r| r.is_ok())
+            ),
+        }
+    }
+
+    fn read_all(input: &str) -> Vec<Puzzle> {
+        read(input.as_bytes(), Format::Auto)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn line_errors() {
+        let err = parse_error(&EMPTY[1..], Format::Line);
+        assert_eq!(
+            err.reason,
+            ParseErrorReason::WrongLength {
+                expected: 81,
+                found: 80
+            }
+        );
+        let err = parse_error(&format!("{}x", &EMPTY[1..]), Format::Line);
+        assert_eq!(err.reason, ParseErrorReason::InvalidChar('x'));
+        assert_eq!((err.line, err.column), (1, 81));
+        let err = parse_error(&format!("{}0", &EMPTY[1..]), Format::Line);
+        assert_eq!(err.reason, ParseErrorReason::ZeroDigit);
+        assert!("12".parse::<Puzzle>().is_err());
+    }
+
+    #[test]
+    fn grid_errors() {
+        let rows = "1.. ... ...\n... ... ...\n";
+        let err = parse_error(rows, Format::Grid);
+        assert_eq!(
+            err.reason,
+            ParseErrorReason::Incomplete {
+                rows: 2,
+                expected: 9
+            }
+        );
+        let err = parse_error("{\"puzzle\": 1}", Format::Json);
+        assert!(matches!(err.reason, ParseErrorReason::Json(_)));
+    }
+
+    #[test]
+    fn declaration_errors() {
+        let cases = [
+            ("variant: sideways", "Variant"),
+            ("cage: 3 r1c1 r1c1", "Cage"),
+            ("cage: 3 r1c1\ncage: 4 r1c1 r1c2", "Cage"),
+            ("regions: 123", "Regions"),
+            ("constraints: {\"type\": \"thermo\"", "Constraint"),
+            (
+                "constraints: {\"type\": \"sandwich\", \"row\": 1, \"sum\": 100}",
+                "Constraint",
+            ),
+            ("layout: pyramid", "Layout"),
+        ];
+        for (declaration, reason) in &cases {
+            let err = parse_error(&format!("{}\n{}\n", declaration, EMPTY), Format::Auto);
+            assert!(
+                format!("{:?}", err.reason).starts_with(reason),
+                "{}: {:?}",
+                declaration,
+                err
+            );
+            assert_eq!(err.line, 1 + declaration.matches('\n').count());
+        }
+    }
+
+    #[test]
+    fn layout_errors_point_at_the_declaration() {
+        for layout in &["layout: 0,0 1,0", "layout: 0,0 0,0", "layout: 0,0 30,30"] {
+            let input = format!("{}\n{}\n{}\n", layout, EMPTY, EMPTY);
+            let err = parse_error(&input, Format::Auto);
+            assert!(
+                matches!(err.reason, ParseErrorReason::Layout(_)),
+                "{:?}",
+                err
+            );
+            assert_eq!(err.line, 1, "{}", layout);
+        }
+        // Grids disagreeing on a shared cell are reported where they end.
+        // r7c7 is the first cell of the second grid.
+        let first = format!("{}1{}", &EMPTY[..60], &EMPTY[61..]);
+        let second = format!("2{}", &EMPTY[1..]);
+        let input = format!("layout: twodoku\n{}\n{}\n", first, second);
+        assert_eq!(parse_error(&input, Format::Auto).line, 3);
+    }
+
+    #[test]
+    fn display_reads_back() {
+        let input = [
+            "variant: diagonal, anti-king",
+            "regions: 111123333111222233122225333144445666444555666745555669748888699777888899777789999",
+            "cage: 3 r1c1 r1c2",
+            "cage: 17 r5c5 r5c6 r6c5",
+            "constraints: [{\"type\": \"thermo\", \"cells\": [\"r9c1\", \"r9c2\"]}]",
+            EMPTY,
+        ]
+        .join("\n");
+        let puzzle = read_all(&input).remove(0);
+        let written = puzzle.to_string();
+        let again = read_all(&written);
+        assert_eq!(again.len(), 1);
+        assert_eq!(again[0].declarations(), puzzle.declarations());
+        assert_eq!(again[0].hints, puzzle.hints);
+        // The variants do not carry over to the next puzzle.
+        let next = read_all(&format!("{}\n{}", written, EMPTY));
+        assert!(next[1].variants.is_empty());
+    }
+
+    #[test]
+    fn layout_display_reads_back() {
+        let grid = format!("1{}", &EMPTY[1..]);
+        let puzzles = read_all(&format!("layout: twodoku\n{}\n{}", grid, grid));
+        let written = puzzles[0].to_string();
+        assert!(written.starts_with("layout: 0,0 6,6\n"));
+        let again = read_all(&format!("{}\n{}", written, EMPTY));
+        assert_eq!(again[0].hints, puzzles[0].hints);
+        assert!(again[1].layout.is_none());
     }
-    Ok(())
 }
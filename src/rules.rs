@@ -0,0 +1,417 @@
+//! The rules a puzzle follows: the houses every digit fills once, the
+//! other cells that may not repeat a digit, the sums of killer cages and
+//! the constraints of arithmetic variants, for the classic grid and its
+//! variants.
+
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use bitmaps::Bitmap;
+
+use crate::constraint::Constraint;
+use crate::house::{House, Shape};
+
+/// Cells in row-major order, large enough for the largest grids.
+pub type CellSet = Bitmap<1024>;
+
+/// Rules added on top of the rows, columns and blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// Both main diagonals hold every digit once, also known as Sudoku-X.
+    Diagonal,
+    /// Cells a knight's move apart never hold the same digit.
+    AntiKnight,
+    /// Cells a king's move apart never hold the same digit.
+    AntiKing,
+    /// Orthogonally adjacent cells never hold consecutive digits.
+    NonConsecutive,
+}
+
+impl Variant {
+    pub const ALL: &'static [Variant] = &[
+        Variant::Diagonal,
+        Variant::AntiKnight,
+        Variant::AntiKing,
+        Variant::NonConsecutive,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Diagonal => "diagonal",
+            Variant::AntiKnight => "anti-knight",
+            Variant::AntiKing => "anti-king",
+            Variant::NonConsecutive => "non-consecutive",
+        }
+    }
+
+    /// Parses a list of variants separated by commas or spaces, such as
+    /// `diagonal, anti-knight`. `none` stands for no variants.
+    pub fn parse_list(list: &str) -> Result<Vec<Variant>, String> {
+        let mut variants = Vec::new();
+        for name in list.split(|c: char| c == ',' || c.is_whitespace()) {
+            if name.is_empty() || name == "none" {
+                continue;
+            }
+            let variant = name.parse()?;
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+        Ok(variants)
+    }
+
+    /// The offsets of the cells the variant forbids repeating a digit in.
+    fn peer_offsets(self) -> &'static [(i32, i32)] {
+        match self {
+            Variant::AntiKnight => &[
+                (1, 2),
+                (2, 1),
+                (2, -1),
+                (1, -2),
+                (-1, -2),
+                (-2, -1),
+                (-2, 1),
+                (-1, 2),
+            ],
+            Variant::AntiKing => &[(1, 1), (1, -1), (-1, -1), (-1, 1)],
+            Variant::Diagonal | Variant::NonConsecutive => &[],
+        }
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Variant, String> {
+        Variant::ALL
+            .iter()
+            .find(|variant| variant.name() == name)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "unknown variant '{}', expected one of diagonal, anti-knight, anti-king, non-consecutive",
+                    name
+                )
+            })
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The position of `house` in the houses of a grid, see [`Rules::houses`].
+fn house_index(shape: Shape, house: House) -> usize {
+    let size = shape.size() as usize;
+    match house {
+        House::Block(b) => b as usize,
+        House::Row(y) => size + y as usize,
+        House::Column(x) => 2 * size + x as usize,
+        House::Diagonal => 3 * size,
+        House::AntiDiagonal => 3 * size + 1,
+    }
+}
+
+/// A killer cage: cells with different digits that add up to `sum`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cage {
+    pub sum: i32,
+    pub cells: Vec<(i32, i32)>,
+}
+
+impl Cage {
+    /// The sets of different digits from 1 to `size` with one digit per cell
+    /// that add up to the sum, as masks with bit `digit - 1` set.
+    pub fn combinations(&self, size: i32) -> Vec<u32> {
+        fn add(from: i32, size: i32, count: i32, sum: i32, mask: u32, combinations: &mut Vec<u32>) {
+            if count == 0 {
+                if sum == 0 {
+                    combinations.push(mask);
+                }
+                return;
+            }
+            for digit in from..=size {
+                // The smallest digits left already add up to too much.
+                if digit * count + count * (count - 1) / 2 > sum {
+                    break;
+                }
+                add(
+                    digit + 1,
+                    size,
+                    count - 1,
+                    sum - digit,
+                    mask | 1 << (digit - 1),
+                    combinations,
+                );
+            }
+        }
+        let mut combinations = Vec::new();
+        add(
+            1,
+            size,
+            self.cells.len() as i32,
+            self.sum,
+            0,
+            &mut combinations,
+        );
+        combinations
+    }
+}
+
+/// The houses and peers of every cell, worked out once per puzzle.
+#[derive(Clone, Debug)]
+pub struct Rules {
+    shape: Shape,
+    variants: Vec<Variant>,
+    cages: Vec<Cage>,
+    /// The digit combinations of each cage.
+    combinations: Vec<Vec<u32>>,
+    constraints: Vec<Rc<dyn Constraint>>,
+    /// Whether the blocks were given rather than worked out from the shape.
+    jigsaw: bool,
+    /// The block of each cell.
+    blocks: Vec<i32>,
+    houses: Vec<House>,
+    /// The cells of each house, indexed like `houses`.
+    house_cells: Vec<Vec<(i32, i32)>>,
+    /// Indexed by cell in row-major order, like the fields below.
+    houses_of: Vec<Vec<House>>,
+    /// The cells that may not hold the same digit as the cell.
+    peers: Vec<CellSet>,
+    /// The peers that share no house with the cell.
+    extra_peers: Vec<Vec<(i32, i32)>>,
+    /// The cells that may not hold a digit next to the one in the cell.
+    neighbours: Vec<Vec<(i32, i32)>>,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules::new(Shape::CLASSIC, &[], &[])
+    }
+}
+
+impl Rules {
+    pub fn new(shape: Shape, variants: &[Variant], cages: &[Cage]) -> Rules {
+        let blocks: Vec<i32> = (0..shape.num_cells())
+            .map(|i| {
+                let (x, y) = shape.cell(i);
+                shape.block(x, y)
+            })
+            .collect();
+        Rules::with_blocks(shape, blocks, false, variants, cages)
+    }
+
+    /// The rules of a jigsaw sudoku, where `regions` holds the block of each
+    /// cell in row-major order, numbered from 0. Every block must have as
+    /// many cells as a row.
+    pub fn jigsaw(shape: Shape, regions: &[i32], variants: &[Variant], cages: &[Cage]) -> Rules {
+        Rules::with_blocks(shape, regions.to_vec(), true, variants, cages)
+    }
+
+    fn with_blocks(
+        shape: Shape,
+        blocks: Vec<i32>,
+        jigsaw: bool,
+        variants: &[Variant],
+        cages: &[Cage],
+    ) -> Rules {
+        let size = shape.size();
+        let mut houses: Vec<House> = House::all(shape).collect();
+        if variants.contains(&Variant::Diagonal) {
+            houses.push(House::Diagonal);
+            houses.push(House::AntiDiagonal);
+        }
+        let cells: Vec<(i32, i32)> = (0..shape.num_cells()).map(|i| shape.cell(i)).collect();
+        let house_cells: Vec<Vec<(i32, i32)>> = houses
+            .iter()
+            .map(|&house| match house {
+                House::Block(b) => cells
+                    .iter()
+                    .zip(&blocks)
+                    .filter(|&(_, &block)| block == b)
+                    .map(|(&cell, _)| cell)
+                    .collect(),
+                _ => house.cells(shape).collect(),
+            })
+            .collect();
+        let houses_of: Vec<Vec<House>> = cells
+            .iter()
+            .zip(&blocks)
+            .map(|(&(x, y), &block)| {
+                let mut of = vec![House::Row(y), House::Column(x), House::Block(block)];
+                of.extend(
+                    houses[3 * size as usize..]
+                        .iter()
+                        .filter(|house| house.contains(shape, x, y)),
+                );
+                of
+            })
+            .collect();
+        let inside = |(x, y): (i32, i32)| (0..size).contains(&x) && (0..size).contains(&y);
+        let mut peers = Vec::with_capacity(cells.len());
+        let mut extra_peers = Vec::with_capacity(cells.len());
+        for (i, &(x, y)) in cells.iter().enumerate() {
+            let mut mask = CellSet::new();
+            for house in &houses_of[i] {
+                for &(px, py) in &house_cells[house_index(shape, *house)] {
+                    mask.set(shape.index(px, py), true);
+                }
+            }
+            let mut extra = Vec::new();
+            let variant_peers = variants.iter().flat_map(|variant| {
+                variant
+                    .peer_offsets()
+                    .iter()
+                    .map(move |&(dx, dy)| (x + dx, y + dy))
+            });
+            let cage_peers = cages
+                .iter()
+                .filter(|cage| cage.cells.contains(&(x, y)))
+                .flat_map(|cage| cage.cells.iter().copied());
+            for peer in variant_peers.chain(cage_peers) {
+                if inside(peer) && peer != (x, y) && !mask.get(shape.index(peer.0, peer.1)) {
+                    mask.set(shape.index(peer.0, peer.1), true);
+                    extra.push(peer);
+                }
+            }
+            mask.set(i, false);
+            peers.push(mask);
+            extra_peers.push(extra);
+        }
+        let neighbours = cells
+            .iter()
+            .map(|&(x, y)| {
+                if !variants.contains(&Variant::NonConsecutive) {
+                    return Vec::new();
+                }
+                [(x, y - 1), (x - 1, y), (x + 1, y), (x, y + 1)]
+                    .iter()
+                    .copied()
+                    .filter(|&cell| inside(cell))
+                    .collect()
+            })
+            .collect();
+        Rules {
+            shape,
+            variants: variants.to_vec(),
+            cages: cages.to_vec(),
+            combinations: cages.iter().map(|cage| cage.combinations(size)).collect(),
+            constraints: Vec::new(),
+            jigsaw,
+            blocks,
+            houses,
+            house_cells,
+            houses_of,
+            peers,
+            extra_peers,
+            neighbours,
+        }
+    }
+
+    /// Adds constraints between the digits of some cells, which must be
+    /// inside the grid.
+    pub fn with_constraints(mut self, constraints: &[Rc<dyn Constraint>]) -> Self {
+        self.constraints.extend_from_slice(constraints);
+        self
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn variants(&self) -> &[Variant] {
+        &self.variants
+    }
+
+    pub fn cages(&self) -> &[Cage] {
+        &self.cages
+    }
+
+    pub fn constraints(&self) -> &[Rc<dyn Constraint>] {
+        &self.constraints
+    }
+
+    /// Whether only the rows, columns and blocks constrain the digits.
+    pub fn is_classic(&self) -> bool {
+        self.variants.is_empty() && self.cages.is_empty() && self.constraints.is_empty()
+    }
+
+    /// Every house, blocks first, then rows, columns and the others.
+    pub fn houses(&self) -> &[House] {
+        &self.houses
+    }
+
+    /// The cells of `house` in reading order.
+    pub fn house_cells(&self, house: House) -> &[(i32, i32)] {
+        &self.house_cells[house_index(self.shape, house)]
+    }
+
+    /// Whether the blocks have arbitrary shapes instead of being rectangles
+    /// of the shape's size.
+    pub fn is_jigsaw(&self) -> bool {
+        self.jigsaw
+    }
+
+    /// The block of the cell at `(x, y)`.
+    pub fn block(&self, x: i32, y: i32) -> i32 {
+        self.blocks[self.shape.index(x, y)]
+    }
+
+    pub fn contains(&self, house: House, x: i32, y: i32) -> bool {
+        match house {
+            House::Block(b) => self.block(x, y) == b,
+            _ => house.contains(self.shape, x, y),
+        }
+    }
+
+    /// The houses containing the cell at `(x, y)`.
+    pub fn houses_of(&self, x: i32, y: i32) -> &[House] {
+        &self.houses_of[self.shape.index(x, y)]
+    }
+
+    /// The cells that may not hold the same digit as the cell at `(x, y)`.
+    pub fn peers(&self, x: i32, y: i32) -> &CellSet {
+        &self.peers[self.shape.index(x, y)]
+    }
+
+    /// The peers of the cell at `(x, y)` outside its houses.
+    pub fn extra_peers(&self, x: i32, y: i32) -> &[(i32, i32)] {
+        &self.extra_peers[self.shape.index(x, y)]
+    }
+
+    /// The cells that may not hold a digit one above or below the digit of
+    /// the cell at `(x, y)`.
+    pub fn neighbours(&self, x: i32, y: i32) -> &[(i32, i32)] {
+        &self.neighbours[self.shape.index(x, y)]
+    }
+
+    /// The digits of each cell of a cage that are part of a combination
+    /// adding up to its sum, given the `possibles` of its cells in order.
+    /// Combinations whose digits cannot all be placed are left out, without
+    /// checking whether every cell can get a different one.
+    pub fn cage_possibles(&self, cage: usize, possibles: &[u32]) -> Vec<u32> {
+        let mut allowed = vec![0; possibles.len()];
+        for &combination in &self.combinations[cage] {
+            let mut covered = 0;
+            if possibles.iter().any(|&mask| {
+                covered |= mask & combination;
+                mask & combination == 0
+            }) || covered != combination
+            {
+                continue;
+            }
+            for (allowed, &mask) in allowed.iter_mut().zip(possibles) {
+                *allowed |= mask & combination;
+            }
+        }
+        allowed
+    }
+
+    /// Whether two cells may not hold the same digit.
+    pub fn sees(&self, a: (i32, i32), b: (i32, i32)) -> bool {
+        self.peers(a.0, a.1).get(self.shape.index(b.0, b.1))
+    }
+}
@@ -1,8 +1,29 @@
 use crate::cell::{Cell, EliminationError};
+use crate::house::{House, Shape};
 use crate::observer::{GridObserver, SolverObserver};
-use bitmaps::Bitmap;
+use crate::random::Random;
+use crate::rules::{CellSet, Rules};
+use crate::strategy::{next_deduction, Candidates, Deduction, Technique};
+use std::rc::Rc;
+use std::str::FromStr;
 
 pub trait Grid: Clone + std::fmt::Debug {
+    fn shape(&self) -> Shape;
+    fn rules(&self) -> Rc<Rules>;
+    /// The number of cells, more than the shape has for grids made of
+    /// several overlapping ones.
+    fn num_cells(&self) -> usize {
+        self.shape().num_cells()
+    }
+    /// The position of the cell numbered `index` in row-major order.
+    fn position(&self, index: usize) -> (i32, i32) {
+        self.shape().cell(index)
+    }
+    /// The number of columns, sets of cells hold the cell at `(x, y)` as
+    /// `y * width + x`.
+    fn width(&self) -> i32 {
+        self.shape().size()
+    }
     fn cell_mut(&mut self, x: i32, y: i32) -> &mut Cell;
     fn cell(&self, x: i32, y: i32) -> &Cell;
     fn set_hint(&mut self, x: i32, y: i32, hint: i32);
@@ -12,113 +33,150 @@ pub trait Grid: Clone + std::fmt::Debug {
         y: i32,
         digit: i32,
         mark_solved: &mut F,
-    ) -> Result<(), EliminationError>;
+    ) -> Result<usize, EliminationError>;
+    /// Eliminates the digits of killer cages that are in no combination
+    /// adding up to the sum, and those the constraints rule out, until there
+    /// are none left.
+    fn prune<F: FnMut((i32, i32))>(
+        &mut self,
+        mark_solved: &mut F,
+    ) -> Result<usize, EliminationError>;
+    /// Eliminates a single candidate, returning whether it was possible.
+    fn eliminate_candidate(&mut self, x: i32, y: i32, digit: i32)
+        -> Result<bool, EliminationError>;
     fn invalidate(&self);
     fn dump_solution(&self) -> Option<Vec<i32>>;
+    /// The candidates the techniques look at, with the position of the top
+    /// left cell of each grid they cover.
+    fn sub_grids(&self) -> Vec<((i32, i32), Candidates)> {
+        vec![((0, 0), Candidates::from_grid(self))]
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct ObserveableGrid<TObserver: GridObserver> {
-    cells: Vec<Cell>, // Should have exactly 81 elements
+    shape: Shape,
+    rules: Rc<Rules>,
+    cells: Vec<Cell>, // Should have exactly shape.num_cells() elements
     observer: TObserver,
 }
 
 impl<TObserver: GridObserver> ObserveableGrid<TObserver> {
     pub fn new(observer: TObserver) -> ObserveableGrid<TObserver> {
+        ObserveableGrid::with_shape(observer, Shape::CLASSIC)
+    }
+
+    pub fn with_shape(observer: TObserver, shape: Shape) -> ObserveableGrid<TObserver> {
+        ObserveableGrid::with_rules(observer, Rc::new(Rules::new(shape, &[], &[])))
+    }
+
+    /// A grid following `rules`, which may add houses and peers to the
+    /// rows, columns and blocks.
+    pub fn with_rules(observer: TObserver, rules: Rc<Rules>) -> ObserveableGrid<TObserver> {
+        let shape = rules.shape();
         ObserveableGrid {
-            cells: (0..81).map(|_| Cell::new()).collect(),
+            shape,
+            rules,
+            cells: (0..shape.num_cells())
+                .map(|_| Cell::with_digits(shape.size()))
+                .collect(),
             observer,
         }
     }
 
-    fn eliminate_in_block<F: FnMut((i32, i32))>(
+    fn eliminate_in_house<F: FnMut((i32, i32))>(
         &mut self,
+        rules: &Rules,
+        house: House,
         x: i32,
         y: i32,
         digit: i32,
         mark_solved: &mut F,
-    ) -> Result<(), EliminationError> {
-        let block_start_x = (x / 3) * 3;
-        let block_start_y = (y / 3) * 3;
-        let _highlight = self.observer.highlight_block(block_start_x, block_start_y);
+    ) -> Result<usize, EliminationError> {
+        let _highlight = match house {
+            House::Block(b) if !rules.is_jigsaw() => {
+                let (block_start_x, block_start_y) = self.shape.block_origin(b);
+                self.observer.highlight_block(block_start_x, block_start_y)
+            }
+            House::Row(y) => self.observer.highlight_row(y),
+            House::Column(x) => self.observer.highlight_column(x),
+            _ => Default::default(),
+        };
         self.observer.highlight_cell(x, y, self.cell(x, y), true);
-        for y_mod in block_start_y..block_start_y + 3 {
-            for x_mod in block_start_x..block_start_x + 3 {
-                if x_mod == x && y_mod == y {
-                    continue;
-                }
-                self.observer
-                    .highlight_cell(x_mod, y_mod, self.cell(x_mod, y_mod), false);
-                self.cell_mut(x_mod, y_mod).eliminate_possible(digit)?;
-                self.observer
-                    .clear_cell(x_mod, y_mod, self.cell(x_mod, y_mod));
-                if self.cell(x_mod, y_mod).num_possibles() == 1 {
-                    mark_solved((x_mod, y_mod));
-                }
+        let mut eliminated = 0;
+        for &(x_mod, y_mod) in rules.house_cells(house) {
+            if x_mod == x && y_mod == y {
+                continue;
+            }
+            if self.eliminate_in_cell(x_mod, y_mod, digit, mark_solved)? {
+                eliminated += 1;
             }
         }
         self.observer.clear_cell(x, y, self.cell(x, y));
-        Ok(())
+        Ok(eliminated)
     }
 
-    fn eliminate_in_row<F: FnMut((i32, i32))>(
+    fn eliminate_in_cell<F: FnMut((i32, i32))>(
         &mut self,
         x: i32,
         y: i32,
         digit: i32,
         mark_solved: &mut F,
-    ) -> Result<(), EliminationError> {
-        let _highlight = self.observer.highlight_row(y);
-        self.observer.highlight_cell(x, y, self.cell(x, y), true);
-        for x_mod in 0..9 {
-            if x_mod == x {
-                continue;
-            }
-            self.observer
-                .highlight_cell(x_mod, y, self.cell(x_mod, y), false);
-            self.cell_mut(x_mod, y).eliminate_possible(digit)?;
-            self.observer.clear_cell(x_mod, y, self.cell(x_mod, y));
-            if self.cell(x_mod, y).num_possibles() == 1 {
-                mark_solved((x_mod, y));
-            }
-        }
+    ) -> Result<bool, EliminationError> {
+        self.observer.highlight_cell(x, y, self.cell(x, y), false);
+        let eliminated = self.cell_mut(x, y).eliminate_possible(digit)?;
         self.observer.clear_cell(x, y, self.cell(x, y));
-        Ok(())
+        if self.cell(x, y).num_possibles() == 1 {
+            mark_solved((x, y));
+        }
+        Ok(eliminated)
+    }
+
+    /// The candidates of `cells` as masks.
+    fn possibles(&self, cells: &[(i32, i32)]) -> Vec<u32> {
+        cells
+            .iter()
+            .map(|&(x, y)| self.cell(x, y).possibles_mask())
+            .collect()
     }
 
-    fn eliminate_in_column<F: FnMut((i32, i32))>(
+    /// Eliminates the `possibles` of `cells` that are not `allowed`.
+    fn keep_allowed<F: FnMut((i32, i32))>(
         &mut self,
-        x: i32,
-        y: i32,
-        digit: i32,
+        cells: &[(i32, i32)],
+        possibles: &[u32],
+        allowed: &[u32],
         mark_solved: &mut F,
-    ) -> Result<(), EliminationError> {
-        let _highlight = self.observer.highlight_column(x);
-        self.observer.highlight_cell(x, y, self.cell(x, y), true);
-        for y_mod in 0..9 {
-            if y_mod == y {
-                continue;
-            }
-            self.observer
-                .highlight_cell(x, y_mod, self.cell(x, y_mod), false);
-            self.cell_mut(x, y_mod).eliminate_possible(digit)?;
-            self.observer.clear_cell(x, y_mod, self.cell(x, y_mod));
-            if self.cell(x, y_mod).num_possibles() == 1 {
-                mark_solved((x, y_mod));
+    ) -> Result<usize, EliminationError> {
+        let mut eliminated = 0;
+        for (j, &(x, y)) in cells.iter().enumerate() {
+            let mut excluded = possibles[j] & !allowed[j];
+            while excluded != 0 {
+                let digit = excluded.trailing_zeros() as i32 + 1;
+                excluded &= excluded - 1;
+                self.eliminate_in_cell(x, y, digit, mark_solved)?;
+                eliminated += 1;
             }
         }
-        self.observer.clear_cell(x, y, self.cell(x, y));
-        Ok(())
+        Ok(eliminated)
     }
 }
 
 impl<TObserver: GridObserver> Grid for ObserveableGrid<TObserver> {
+    fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    fn rules(&self) -> Rc<Rules> {
+        self.rules.clone()
+    }
+
     fn cell_mut(&mut self, x: i32, y: i32) -> &mut Cell {
-        &mut self.cells[(y * 9 + x) as usize]
+        &mut self.cells[self.shape.index(x, y)]
     }
 
     fn cell(&self, x: i32, y: i32) -> &Cell {
-        &self.cells[(y * 9 + x) as usize]
+        &self.cells[self.shape.index(x, y)]
     }
 
     fn set_hint(&mut self, x: i32, y: i32, hint: i32) {
@@ -133,18 +191,70 @@ impl<TObserver: GridObserver> Grid for ObserveableGrid<TObserver> {
         y: i32,
         digit: i32,
         mark_solved: &mut F,
-    ) -> Result<(), EliminationError> {
-        self.eliminate_in_block(x, y, digit, mark_solved)?;
-        self.eliminate_in_row(x, y, digit, mark_solved)?;
-        self.eliminate_in_column(x, y, digit, mark_solved)?;
-        Ok(())
+    ) -> Result<usize, EliminationError> {
+        let rules = self.rules.clone();
+        let mut eliminated = 0;
+        for &house in rules.houses_of(x, y) {
+            eliminated += self.eliminate_in_house(&rules, house, x, y, digit, mark_solved)?;
+        }
+        for &(peer_x, peer_y) in rules.extra_peers(x, y) {
+            if self.eliminate_in_cell(peer_x, peer_y, digit, mark_solved)? {
+                eliminated += 1;
+            }
+        }
+        for &(next_x, next_y) in rules.neighbours(x, y) {
+            for next in [digit - 1, digit + 1].iter().copied() {
+                if (1..=self.shape.size()).contains(&next)
+                    && self.eliminate_in_cell(next_x, next_y, next, mark_solved)?
+                {
+                    eliminated += 1;
+                }
+            }
+        }
+        Ok(eliminated)
+    }
+
+    fn prune<F: FnMut((i32, i32))>(
+        &mut self,
+        mark_solved: &mut F,
+    ) -> Result<usize, EliminationError> {
+        let rules = self.rules.clone();
+        let mut eliminated = 0;
+        loop {
+            let before = eliminated;
+            for (i, cage) in rules.cages().iter().enumerate() {
+                let possibles = self.possibles(&cage.cells);
+                let allowed = rules.cage_possibles(i, &possibles);
+                eliminated += self.keep_allowed(&cage.cells, &possibles, &allowed, mark_solved)?;
+            }
+            for constraint in rules.constraints() {
+                let possibles = self.possibles(constraint.cells());
+                let allowed = constraint.prune(self.shape.size(), &possibles)?;
+                eliminated +=
+                    self.keep_allowed(constraint.cells(), &possibles, &allowed, mark_solved)?;
+            }
+            if eliminated == before {
+                return Ok(eliminated);
+            }
+        }
+    }
+
+    fn eliminate_candidate(
+        &mut self,
+        x: i32,
+        y: i32,
+        digit: i32,
+    ) -> Result<bool, EliminationError> {
+        self.observer.highlight_cell(x, y, self.cell(x, y), true);
+        let eliminated = self.cell_mut(x, y).eliminate_possible(digit)?;
+        self.observer.clear_cell(x, y, self.cell(x, y));
+        Ok(eliminated)
     }
 
     fn invalidate(&self) {
-        for y in 0..9 {
-            for x in 0..9 {
-                self.observer.clear_cell(x, y, self.cell(x, y));
-            }
+        for i in 0..self.shape.num_cells() {
+            let (x, y) = self.shape.cell(i);
+            self.observer.clear_cell(x, y, self.cell(x, y));
         }
     }
 
@@ -166,89 +276,403 @@ pub struct Guess {
     pub remaining_possibles: Vec<i32>,
 }
 
+/// How solving a puzzle ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SolveOutcome {
+    Solved(Vec<i32>),
+    /// The hints contradict each other.
+    Unsolvable,
+    /// Only reported when checking for uniqueness, holds two of the solutions.
+    MultipleSolutions(Vec<i32>, Vec<i32>),
+    /// The guess limit was reached before the search finished.
+    Aborted,
+}
+
+impl SolveOutcome {
+    /// The solution, if the puzzle has exactly one.
+    pub fn solution(&self) -> Option<&[i32]> {
+        match self {
+            SolveOutcome::Solved(solution) => Some(solution),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SolveStats {
+    /// Candidates eliminated from cells.
+    pub eliminations: usize,
+    /// Logical deductions applied before guessing.
+    pub deductions: usize,
+    /// Guesses made, including the alternatives tried after backtracking.
+    pub guesses: usize,
+    /// Guesses undone after running into a contradiction.
+    pub backtracks: usize,
+    /// Largest number of guesses stacked at once.
+    pub max_depth: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SolveReport {
+    pub outcome: SolveOutcome,
+    pub stats: SolveStats,
+}
+
 pub trait Solver {
     fn set_hint(&mut self, x: i32, y: i32, hint: i32);
-    fn solve(&mut self) -> Option<Vec<i32>>;
+    fn solve(&mut self) -> SolveReport;
+    /// Counts the solutions of the puzzle, stopping once `limit` are found.
+    /// A proper puzzle gives 1 with a limit of 2.
+    fn count_solutions(&mut self, limit: usize) -> usize;
+}
+
+/// The search engines implementing `Solver`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// `SudokuSolver`, which eliminates candidates and applies the logical
+    /// techniques before guessing and can draw its progress.
+    Elimination,
+    /// `DlxSolver`, an exact cover search with dancing links.
+    Dlx,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Backend, String> {
+        match name {
+            "elimination" => Ok(Backend::Elimination),
+            "dlx" => Ok(Backend::Dlx),
+            _ => Err(format!(
+                "unknown backend '{}', expected elimination or dlx",
+                name
+            )),
+        }
+    }
 }
 
 pub struct SudokuSolver<TGrid: Grid, TObserver: SolverObserver> {
     grid: TGrid,
     observer: TObserver,
     cells_to_eliminate: Vec<(i32, i32)>,
+    techniques: Vec<Technique>,
+    check_unique: bool,
+    assume_unique: bool,
+    guess_limit: Option<usize>,
+    random: Option<Random>,
+    stats: SolveStats,
+    /// The cells set with `set_hint`, as opposed to guessed or deduced.
+    hints: Vec<(i32, i32)>,
 }
 
 #[derive(Debug)]
 struct SolverState<TGrid: Grid> {
     guess: Guess,
     grid: TGrid,
-    solved_cells: Bitmap<81>,
+    solved_cells: CellSet,
     cells_to_eliminate: Vec<(i32, i32)>,
 }
 
+/// Why the search stopped.
+#[derive(PartialEq)]
+enum SearchEnd {
+    /// Every branch was searched.
+    Exhausted,
+    /// The solution callback asked to stop.
+    Stopped,
+    /// The guess limit was reached.
+    Aborted,
+}
+
 impl<TGrid: Grid, TObserver: SolverObserver> Solver for SudokuSolver<TGrid, TObserver> {
     fn set_hint(&mut self, x: i32, y: i32, hint: i32) {
-        self.grid.set_hint(x, y, hint);
-        if !self.cells_to_eliminate.contains(&(x, y)) {
-            self.cells_to_eliminate.push((x, y));
+        self.place(x, y, hint);
+        if !self.hints.contains(&(x, y)) {
+            self.hints.push((x, y));
+        }
+    }
+
+    fn solve(&mut self) -> SolveReport {
+        let limit = if self.check_unique { 2 } else { 1 };
+        // Looking for a second solution must not assume there is none.
+        let assume_unique = self.assume_unique && !self.check_unique;
+        let mut solutions = Vec::new();
+        let end = self.search(assume_unique, |found| {
+            solutions.push(found);
+            solutions.len() < limit
+        });
+        let outcome = match (end, solutions.len()) {
+            (_, 2) => {
+                let second = solutions.pop().unwrap();
+                SolveOutcome::MultipleSolutions(solutions.pop().unwrap(), second)
+            }
+            (SearchEnd::Aborted, _) => SolveOutcome::Aborted,
+            (_, 1) => SolveOutcome::Solved(solutions.pop().unwrap()),
+            _ => SolveOutcome::Unsolvable,
+        };
+        SolveReport {
+            outcome,
+            stats: self.stats.clone(),
         }
     }
 
-    fn solve(&mut self) -> Option<Vec<i32>> {
-        let mut solved_cells = Bitmap::<81>::new();
+    fn count_solutions(&mut self, limit: usize) -> usize {
+        let mut count = 0;
+        if limit > 0 {
+            self.search(false, |_| {
+                count += 1;
+                count < limit
+            });
+        }
+        count
+    }
+}
+
+impl<TGrid: Grid, TObserver: SolverObserver> SudokuSolver<TGrid, TObserver> {
+    pub fn new(grid: TGrid, observer: TObserver) -> SudokuSolver<TGrid, TObserver> {
+        SudokuSolver {
+            grid,
+            observer,
+            cells_to_eliminate: Vec::new(),
+            techniques: Technique::BASIC.to_vec(),
+            check_unique: false,
+            assume_unique: false,
+            guess_limit: None,
+            random: None,
+            stats: SolveStats::default(),
+            hints: Vec::new(),
+        }
+    }
+
+    /// The logical techniques tried, in order, before making a guess, by
+    /// default [`Technique::BASIC`]. With none the solver only eliminates
+    /// solved cells from their peers.
+    pub fn techniques(mut self, techniques: &[Technique]) -> Self {
+        self.techniques = techniques.to_vec();
+        self
+    }
+
+    /// Keep searching after the first solution so that `solve` can report
+    /// puzzles with multiple solutions.
+    pub fn check_unique(mut self, check_unique: bool) -> Self {
+        self.check_unique = check_unique;
+        self
+    }
+
+    /// Allow techniques that assume the puzzle has a single solution, like
+    /// unique rectangles. They are never used when checking for multiple
+    /// solutions or counting them.
+    pub fn assume_unique(mut self, assume_unique: bool) -> Self {
+        self.assume_unique = assume_unique;
+        self
+    }
+
+    /// Give up with `SolveOutcome::Aborted` after this many guesses.
+    pub fn guess_limit(mut self, guess_limit: Option<usize>) -> Self {
+        self.guess_limit = guess_limit;
+        self
+    }
+
+    /// Guess a random cell among those with the fewest candidates and try
+    /// its digits in random order, instead of the first cell and the lowest
+    /// digit. The same seed always makes the same guesses.
+    pub fn seed(mut self, seed: Option<u64>) -> Self {
+        self.random = seed.map(Random::new);
+        self
+    }
+
+    /// Runs the guess and backtrack search, calling `on_solution` for every
+    /// solution found until it returns false. The grid is restored afterwards
+    /// so the solver can be queried again.
+    fn search<F: FnMut(Vec<i32>) -> bool>(
+        &mut self,
+        assume_unique: bool,
+        on_solution: F,
+    ) -> SearchEnd {
+        self.stats = SolveStats::default();
+        let grid = self.grid.clone();
+        let cells_to_eliminate = self.cells_to_eliminate.clone();
+        let end = self.search_from_current(assume_unique, on_solution);
+        self.grid = grid;
+        self.cells_to_eliminate = cells_to_eliminate;
+        end
+    }
+
+    fn search_from_current<F: FnMut(Vec<i32>) -> bool>(
+        &mut self,
+        assume_unique: bool,
+        mut on_solution: F,
+    ) -> SearchEnd {
+        let mut solved_cells = CellSet::new();
         let mut state_stack: Vec<SolverState<TGrid>> = Vec::new();
 
-        while solved_cells.len() < 81 {
+        loop {
             match self.eliminate_all(solved_cells) {
                 Ok(new_solved_cells) => {
                     solved_cells = new_solved_cells;
                 }
                 Err(_) => {
-                    self.backtrack_and_make_new_guess(&mut state_stack, &mut solved_cells);
+                    if let Err(end) =
+                        self.backtrack_and_make_new_guess(&mut state_stack, &mut solved_cells)
+                    {
+                        return end;
+                    }
                     continue;
                 }
             }
-            if solved_cells.len() == 81 {
-                break;
+            if solved_cells.len() == self.grid.num_cells() {
+                let solution = self.grid.dump_solution().expect("All cells are solved");
+                if !on_solution(solution) {
+                    return SearchEnd::Stopped;
+                }
+                // Look for further solutions as if this one was a contradiction.
+                if let Err(end) =
+                    self.backtrack_and_make_new_guess(&mut state_stack, &mut solved_cells)
+                {
+                    return end;
+                }
+                continue;
+            }
+            match self.apply_technique(solved_cells, &state_stack, assume_unique) {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(_) => {
+                    if let Err(end) =
+                        self.backtrack_and_make_new_guess(&mut state_stack, &mut solved_cells)
+                    {
+                        return end;
+                    }
+                    continue;
+                }
+            }
+            if self.guess_limit_reached() {
+                return SearchEnd::Aborted;
             }
+            let guess = match self.find_guess() {
+                Some(guess) => guess,
+                None => return SearchEnd::Aborted,
+            };
+            self.stats.guesses += 1;
             state_stack.push(SolverState {
-                guess: self.find_guess(),
+                guess,
                 grid: self.grid.clone(),
                 cells_to_eliminate: self.cells_to_eliminate.clone(),
-                solved_cells: solved_cells.clone(),
+                solved_cells,
             });
+            self.stats.max_depth = self.stats.max_depth.max(state_stack.len());
 
             let guess: &Guess = &state_stack.last().unwrap().guess;
-            self.set_hint(guess.x, guess.y, guess.digit);
+            self.place(guess.x, guess.y, guess.digit);
             let vec: Vec<Guess> = state_stack
                 .iter()
                 .map(|s| s.guess.clone())
                 .collect::<Vec<Guess>>();
             self.observer.display_guesses(&vec);
         }
-        self.grid.dump_solution()
     }
-}
 
-impl<TGrid: Grid, TObserver: SolverObserver> SudokuSolver<TGrid, TObserver> {
-    pub fn new(grid: TGrid, observer: TObserver) -> SudokuSolver<TGrid, TObserver> {
-        SudokuSolver {
-            grid,
-            observer,
-            cells_to_eliminate: Vec::new(),
+    /// Applies the first deduction found by the techniques, returning whether
+    /// there was one. Techniques assuming a single solution treat the hints
+    /// and the guesses being tried as givens.
+    fn apply_technique(
+        &mut self,
+        solved_cells: CellSet,
+        state_stack: &[SolverState<TGrid>],
+        assume_unique: bool,
+    ) -> Result<bool, EliminationError> {
+        if self.techniques.is_empty() {
+            return Ok(false);
+        }
+        let mut sub_grids = self.grid.sub_grids();
+        if sub_grids.len() > 1 {
+            // One of several overlapping grids can have more solutions than
+            // the whole puzzle, and every grid is tried with a technique
+            // before a harder one.
+            let found = self.techniques.iter().find_map(|&technique| {
+                sub_grids
+                    .iter()
+                    .enumerate()
+                    .find_map(|(g, (origin, candidates))| {
+                        next_deduction(candidates, &[technique], false)
+                            .map(|deduction| deduction.in_grid(g, *origin))
+                    })
+            });
+            return match found {
+                Some(deduction) => self.apply_deduction(deduction, solved_cells),
+                None => Ok(false),
+            };
+        }
+        let (_, mut candidates) = sub_grids.remove(0);
+        if assume_unique {
+            for &(x, y) in &self.hints {
+                candidates.set_given(x, y);
+            }
+            for state in state_stack {
+                candidates.set_given(state.guess.x, state.guess.y);
+            }
+        }
+        match next_deduction(&candidates, &self.techniques, assume_unique) {
+            Some(deduction) => self.apply_deduction(deduction, solved_cells),
+            None => Ok(false),
         }
     }
 
+    /// Places and eliminates what `deduction` found.
+    fn apply_deduction(
+        &mut self,
+        deduction: Deduction,
+        solved_cells: CellSet,
+    ) -> Result<bool, EliminationError> {
+        self.stats.deductions += 1;
+        self.observer.display_deduction(&deduction);
+        for &(x, y, digit) in &deduction.placements {
+            if !self.grid.cell(x, y).is_possible(digit) {
+                return Err(EliminationError {});
+            }
+            self.place(x, y, digit);
+        }
+        for &(x, y, digit) in &deduction.eliminations {
+            if self.grid.eliminate_candidate(x, y, digit)? {
+                self.stats.eliminations += 1;
+                if self.grid.cell(x, y).num_possibles() == 1
+                    && !solved_cells.get((y * self.grid.width() + x) as usize)
+                    && !self.cells_to_eliminate.contains(&(x, y))
+                {
+                    self.cells_to_eliminate.push((x, y));
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn place(&mut self, x: i32, y: i32, digit: i32) {
+        self.grid.set_hint(x, y, digit);
+        if !self.cells_to_eliminate.contains(&(x, y)) {
+            self.cells_to_eliminate.push((x, y));
+        }
+    }
+
+    fn guess_limit_reached(&self) -> bool {
+        self.guess_limit
+            .is_some_and(|limit| self.stats.guesses >= limit)
+    }
+
     // FIXME: ugly procedure should be untangled
+    /// Fails with the reason the search ends if no new guess can be made.
     fn backtrack_and_make_new_guess(
         &mut self,
         state_stack: &mut Vec<SolverState<TGrid>>,
-        solved_cells: &mut Bitmap<81>,
-    ) {
+        solved_cells: &mut CellSet,
+    ) -> Result<(), SearchEnd> {
         // This guess was wrong, can we make a new one?
         while let Some(old_state) = state_stack.pop() {
-            if old_state.guess.remaining_possibles.len() == 0 {
+            self.stats.backtracks += 1;
+            if old_state.guess.remaining_possibles.is_empty() {
                 continue;
             }
+            if self.guess_limit_reached() {
+                return Err(SearchEnd::Aborted);
+            }
             self.grid = old_state.grid;
             *solved_cells = old_state.solved_cells;
             self.cells_to_eliminate = old_state.cells_to_eliminate;
@@ -257,16 +681,8 @@ impl<TGrid: Grid, TObserver: SolverObserver> SudokuSolver<TGrid, TObserver> {
                 .cell_mut(old_state.guess.x, old_state.guess.y)
                 .eliminate_possible(old_state.guess.digit)
                 .expect("Should always be able to eliminate");
-            if self
-                .grid
-                .cell(old_state.guess.x, old_state.guess.y)
-                .num_possibles()
-                == 1
-            {
-                self.cells_to_eliminate
-                    .push((old_state.guess.x, old_state.guess.y));
-            }
             let digit = old_state.guess.remaining_possibles[0];
+            self.stats.guesses += 1;
 
             state_stack.push(SolverState {
                 guess: Guess {
@@ -282,54 +698,92 @@ impl<TGrid: Grid, TObserver: SolverObserver> SudokuSolver<TGrid, TObserver> {
                 },
                 grid: self.grid.clone(),
                 cells_to_eliminate: self.cells_to_eliminate.clone(),
-                solved_cells: solved_cells.clone(),
+                solved_cells: *solved_cells,
             });
+            // The remaining digits are tried when this guess fails too, so
+            // that every branch is only searched once.
+            self.place(old_state.guess.x, old_state.guess.y, digit);
             self.grid.invalidate();
-            break;
+            return Ok(());
         }
+        Err(SearchEnd::Exhausted)
     }
 
-    fn eliminate_all(
-        &mut self,
-        mut solved_cells: Bitmap<81>,
-    ) -> Result<Bitmap<81>, EliminationError> {
-        while let Some((x, y)) = self.cells_to_eliminate.pop() {
-            assert!(!solved_cells.get((y * 9 + x) as usize));
-            solved_cells.set((y * 9 + x) as usize, true);
+    fn eliminate_all(&mut self, mut solved_cells: CellSet) -> Result<CellSet, EliminationError> {
+        let width = self.grid.width();
+        let index = |x: i32, y: i32| (y * width + x) as usize;
+        loop {
+            while let Some((x, y)) = self.cells_to_eliminate.pop() {
+                assert!(!solved_cells.get(index(x, y)));
+                solved_cells.set(index(x, y), true);
+
+                let cells_to_eliminate_ref = &mut self.cells_to_eliminate;
+                let mut push_cell = |pos| {
+                    if !cells_to_eliminate_ref.contains(&pos)
+                        && !solved_cells.get(index(pos.0, pos.1))
+                    {
+                        cells_to_eliminate_ref.push(pos);
+                    }
+                };
 
+                assert!(self.grid.cell(x, y).num_possibles() == 1);
+                let digit = self.grid.cell(x, y).first_possible().unwrap();
+                self.stats.eliminations += self.grid.eliminate(x, y, digit, &mut push_cell)?;
+            }
             let cells_to_eliminate_ref = &mut self.cells_to_eliminate;
             let mut push_cell = |pos| {
-                if !cells_to_eliminate_ref.contains(&pos)
-                    && !solved_cells.get((&pos.1 * 9 + &pos.0) as usize)
+                if !cells_to_eliminate_ref.contains(&pos) && !solved_cells.get(index(pos.0, pos.1))
                 {
                     cells_to_eliminate_ref.push(pos);
                 }
             };
+            self.stats.eliminations += self.grid.prune(&mut push_cell)?;
+            if self.cells_to_eliminate.is_empty() {
+                return Ok(solved_cells);
+            }
+        }
+    }
 
-            assert!(self.grid.cell(x, y).num_possibles() == 1);
-            let digit = self.grid.cell(x, y).first_possible().unwrap();
-            self.grid.eliminate(x, y, digit, &mut push_cell)?;
+    fn find_guess(&mut self) -> Option<Guess> {
+        let grid = &self.grid;
+        let max_digits = grid.shape().size() as usize;
+        if let Some(random) = &mut self.random {
+            let cells = (2..=max_digits).find_map(|num_digits| {
+                let cells: Vec<(i32, i32)> = (0..grid.num_cells())
+                    .map(|i| grid.position(i))
+                    .filter(|&(x, y)| grid.cell(x, y).num_possibles() == num_digits)
+                    .collect();
+                if cells.is_empty() {
+                    None
+                } else {
+                    Some(cells)
+                }
+            })?;
+            let (x, y) = cells[random.below(cells.len())];
+            let mut digits = grid.cell(x, y).possibles();
+            random.shuffle(&mut digits);
+            return Some(Guess {
+                x,
+                y,
+                digit: digits[0],
+                remaining_possibles: digits[1..].to_vec(),
+            });
         }
-        Ok(solved_cells)
-    }
-
-    fn find_guess(&self) -> Guess {
-        for num_digits in 2..10 {
-            for y in 0..9 {
-                for x in 0..9 {
-                    let cell = self.grid.cell(x, y);
-                    if cell.num_possibles() == num_digits {
-                        let digit = cell.first_possible().unwrap();
-                        return Guess {
-                            x,
-                            y,
-                            digit,
-                            remaining_possibles: cell.possibles_except(digit),
-                        };
-                    }
+        for num_digits in 2..=max_digits {
+            for i in 0..grid.num_cells() {
+                let (x, y) = grid.position(i);
+                let cell = grid.cell(x, y);
+                if cell.num_possibles() == num_digits {
+                    let digit = cell.first_possible().unwrap();
+                    return Some(Guess {
+                        x,
+                        y,
+                        digit,
+                        remaining_possibles: cell.possibles_except(digit),
+                    });
                 }
             }
         }
-        panic!("No guess available");
+        None
     }
 }
@@ -0,0 +1,753 @@
+//! Logical solving techniques working on the candidates of every cell.
+//!
+//! Each [`Technique`] looks for one instance of its pattern and describes it
+//! as a [`Deduction`], the digits it places and the candidates it eliminates.
+//! The solver applies deductions until none are found and only then guesses.
+
+mod basic;
+mod cages;
+mod chains;
+mod constraints;
+mod fish;
+mod uniqueness;
+
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::cell::{Cell, EliminationError};
+use crate::house::{cell_name, House, Shape};
+use crate::reader::Puzzle;
+use crate::rules::Rules;
+use crate::solver::Grid;
+
+/// Why the candidates of a puzzle could not be set up.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PuzzleError {
+    /// The hints contradict each other, so there is no solution.
+    NoSolution,
+    /// The puzzle is made of several grids, which only the solver supports.
+    Layout,
+}
+
+impl From<EliminationError> for PuzzleError {
+    fn from(_: EliminationError) -> PuzzleError {
+        PuzzleError::NoSolution
+    }
+}
+
+impl fmt::Display for PuzzleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PuzzleError::NoSolution => write!(f, "puzzle has no solution"),
+            PuzzleError::Layout => write!(f, "puzzles with a layout are not supported"),
+        }
+    }
+}
+
+impl std::error::Error for PuzzleError {}
+
+/// The candidates of every cell. Cells are placed once their digit has been
+/// eliminated from all of their peers. Givens are the placed cells that were
+/// part of the puzzle rather than deduced, which only matters to techniques
+/// assuming a single solution.
+#[derive(Clone, Debug)]
+pub struct Candidates {
+    shape: Shape,
+    rules: Rc<Rules>,
+    cells: Vec<Cell>,
+    placed: Vec<bool>,
+    given: Vec<bool>,
+}
+
+impl Default for Candidates {
+    fn default() -> Candidates {
+        Candidates::new()
+    }
+}
+
+impl Candidates {
+    /// An empty 9x9 grid where every digit is possible in every cell.
+    pub fn new() -> Candidates {
+        Candidates::with_shape(Shape::CLASSIC)
+    }
+
+    /// An empty grid of `shape` where every digit is possible in every cell.
+    pub fn with_shape(shape: Shape) -> Candidates {
+        Candidates::with_rules(Rc::new(Rules::new(shape, &[], &[])))
+    }
+
+    /// An empty grid following `rules`.
+    pub fn with_rules(rules: Rc<Rules>) -> Candidates {
+        let shape = rules.shape();
+        let num_cells = shape.num_cells();
+        Candidates {
+            shape,
+            rules,
+            cells: (0..num_cells)
+                .map(|_| Cell::with_digits(shape.size()))
+                .collect(),
+            placed: vec![false; num_cells],
+            given: vec![false; num_cells],
+        }
+    }
+
+    /// Places the hints of `puzzle`, failing if they contradict each other.
+    /// Puzzles with a layout of several grids are not supported, the solver
+    /// looks at their grids one at a time with `from_cells`.
+    pub fn from_puzzle(puzzle: &Puzzle) -> Result<Candidates, PuzzleError> {
+        if puzzle.layout.is_some() {
+            return Err(PuzzleError::Layout);
+        }
+        let mut candidates = Candidates::with_rules(Rc::new(puzzle.rules()));
+        for (i, hint) in puzzle.hints.iter().enumerate() {
+            if let Some(digit) = hint {
+                let (x, y) = puzzle.shape.cell(i);
+                candidates.place(x, y, *digit)?;
+                candidates.given[i] = true;
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// The candidates of a grid where every solved cell has been eliminated
+    /// from its peers, as is the case whenever the solver runs out of
+    /// eliminations. No cell is given until marked with `set_given`.
+    pub fn from_grid<TGrid: Grid>(grid: &TGrid) -> Candidates {
+        let shape = grid.shape();
+        let cells: Vec<Cell> = (0..shape.num_cells())
+            .map(|i| {
+                let (x, y) = shape.cell(i);
+                grid.cell(x, y).clone()
+            })
+            .collect();
+        Candidates::from_cells(grid.rules(), cells)
+    }
+
+    /// The candidates of cells following `rules`, given in row-major order
+    /// like `from_grid` takes them from a grid.
+    pub fn from_cells(rules: Rc<Rules>, cells: Vec<Cell>) -> Candidates {
+        let shape = rules.shape();
+        let placed = cells.iter().map(|c| c.num_possibles() == 1).collect();
+        Candidates {
+            shape,
+            rules,
+            cells,
+            placed,
+            given: vec![false; shape.num_cells()],
+        }
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Number of rows, columns, blocks and digits.
+    pub fn size(&self) -> i32 {
+        self.shape.size()
+    }
+
+    pub fn cell(&self, x: i32, y: i32) -> &Cell {
+        &self.cells[self.shape.index(x, y)]
+    }
+
+    pub fn is_placed(&self, x: i32, y: i32) -> bool {
+        self.placed[self.shape.index(x, y)]
+    }
+
+    pub fn is_given(&self, x: i32, y: i32) -> bool {
+        self.given[self.shape.index(x, y)]
+    }
+
+    pub fn set_given(&mut self, x: i32, y: i32) {
+        let i = self.shape.index(x, y);
+        self.given[i] = true;
+    }
+
+    /// The houses of the grid, see [`Rules::houses`].
+    pub fn houses(&self) -> impl Iterator<Item = House> + '_ {
+        self.rules.houses().iter().copied()
+    }
+
+    /// The cells of `house` in reading order.
+    pub fn house_cells(&self, house: House) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.rules.house_cells(house).iter().copied()
+    }
+
+    /// The houses containing the cell at `(x, y)`.
+    pub fn houses_of(&self, x: i32, y: i32) -> &[House] {
+        self.rules.houses_of(x, y)
+    }
+
+    /// Whether two different cells may not hold the same digit.
+    pub fn sees(&self, a: (i32, i32), b: (i32, i32)) -> bool {
+        self.rules.sees(a, b)
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.placed.iter().all(|p| *p)
+    }
+
+    /// Whether `digit` is possible in the cell but not yet placed there.
+    pub fn is_open(&self, x: i32, y: i32, digit: i32) -> bool {
+        !self.is_placed(x, y) && self.cell(x, y).is_possible(digit)
+    }
+
+    /// The unplaced cells of `house` where `digit` is possible.
+    pub fn positions(&self, house: House, digit: i32) -> Vec<(i32, i32)> {
+        self.house_cells(house)
+            .filter(|&(x, y)| self.is_open(x, y, digit))
+            .collect()
+    }
+
+    /// Sets the cell to `digit` and eliminates it from the peers, and the
+    /// digits next to it from the neighbours of non-consecutive puzzles.
+    pub fn place(&mut self, x: i32, y: i32, digit: i32) -> Result<(), EliminationError> {
+        if !self.cell(x, y).is_possible(digit) {
+            return Err(EliminationError {});
+        }
+        let i = self.shape.index(x, y);
+        self.cells[i].set_hint(digit);
+        self.placed[i] = true;
+        let rules = self.rules.clone();
+        for house in rules.houses_of(x, y) {
+            for &(peer_x, peer_y) in rules.house_cells(*house) {
+                if (peer_x, peer_y) != (x, y) {
+                    self.eliminate(peer_x, peer_y, digit)?;
+                }
+            }
+        }
+        for &(peer_x, peer_y) in rules.extra_peers(x, y) {
+            self.eliminate(peer_x, peer_y, digit)?;
+        }
+        for &(next_x, next_y) in rules.neighbours(x, y) {
+            for next in [digit - 1, digit + 1].iter().copied() {
+                if (1..=self.size()).contains(&next) {
+                    self.eliminate(next_x, next_y, next)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether `digit` was possible before.
+    pub fn eliminate(&mut self, x: i32, y: i32, digit: i32) -> Result<bool, EliminationError> {
+        let i = self.shape.index(x, y);
+        self.cells[i].eliminate_possible(digit)
+    }
+
+    pub fn apply(&mut self, deduction: &Deduction) -> Result<(), EliminationError> {
+        for &(x, y, digit) in &deduction.placements {
+            self.place(x, y, digit)?;
+        }
+        for &(x, y, digit) in &deduction.eliminations {
+            self.eliminate(x, y, digit)?;
+        }
+        Ok(())
+    }
+
+    pub fn solution(&self) -> Option<Vec<i32>> {
+        self.cells.iter().map(|c| c.solution()).collect()
+    }
+}
+
+/// The first deduction found by `techniques`, tried in order. Techniques
+/// assuming a single solution are skipped unless `assume_unique`, and always
+/// for variants, whose extra rules break the patterns they avoid.
+pub fn next_deduction(
+    candidates: &Candidates,
+    techniques: &[Technique],
+    assume_unique: bool,
+) -> Option<Deduction> {
+    let assume_unique = assume_unique && candidates.rules().is_classic();
+    techniques
+        .iter()
+        .filter(|t| assume_unique || !t.assumes_uniqueness())
+        .find_map(|t| t.find(candidates))
+}
+
+/// The next logical step for `puzzle` using the simplest technique that
+/// finds one, or `None` if it is solved or the techniques are stuck. Fails
+/// if the hints contradict each other or, like `Candidates::from_puzzle`,
+/// if the puzzle has a layout.
+pub fn hint(puzzle: &Puzzle, assume_unique: bool) -> Result<Option<Deduction>, PuzzleError> {
+    let candidates = Candidates::from_puzzle(puzzle)?;
+    Ok(next_deduction(&candidates, Technique::ALL, assume_unique))
+}
+
+/// The logical techniques, from the simplest to the most complex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Technique {
+    HiddenSingle,
+    NakedSingle,
+    CageSum,
+    Constraint,
+    Pointing,
+    BoxLineReduction,
+    NakedPair,
+    XWing,
+    HiddenPair,
+    FinnedXWing,
+    SashimiXWing,
+    NakedTriple,
+    Swordfish,
+    HiddenTriple,
+    FinnedSwordfish,
+    SashimiSwordfish,
+    XYWing,
+    XYZWing,
+    WWing,
+    UniqueRectangleType1,
+    UniqueRectangleType2,
+    UniqueRectangleType3,
+    UniqueRectangleType4,
+    UniqueRectangleType5,
+    UniqueRectangleType6,
+    AvoidableRectangle,
+    NakedQuad,
+    Jellyfish,
+    HiddenQuad,
+    FinnedJellyfish,
+    SashimiJellyfish,
+    BugPlusOne,
+    SimpleColoring,
+    XChain,
+    Aic,
+}
+
+impl Technique {
+    /// Every technique, from the simplest to the most complex, including
+    /// those that assume a single solution.
+    pub const ALL: &'static [Technique] = &[
+        Technique::HiddenSingle,
+        Technique::NakedSingle,
+        Technique::CageSum,
+        Technique::Constraint,
+        Technique::Pointing,
+        Technique::BoxLineReduction,
+        Technique::NakedPair,
+        Technique::XWing,
+        Technique::HiddenPair,
+        Technique::FinnedXWing,
+        Technique::SashimiXWing,
+        Technique::NakedTriple,
+        Technique::Swordfish,
+        Technique::HiddenTriple,
+        Technique::FinnedSwordfish,
+        Technique::SashimiSwordfish,
+        Technique::XYWing,
+        Technique::XYZWing,
+        Technique::WWing,
+        Technique::UniqueRectangleType1,
+        Technique::UniqueRectangleType2,
+        Technique::UniqueRectangleType3,
+        Technique::UniqueRectangleType4,
+        Technique::UniqueRectangleType5,
+        Technique::UniqueRectangleType6,
+        Technique::AvoidableRectangle,
+        Technique::NakedQuad,
+        Technique::Jellyfish,
+        Technique::HiddenQuad,
+        Technique::FinnedJellyfish,
+        Technique::SashimiJellyfish,
+        Technique::BugPlusOne,
+        Technique::SimpleColoring,
+        Technique::XChain,
+        Technique::Aic,
+    ];
+
+    /// The techniques a solver tries by default: singles, cage sums,
+    /// constraints, intersections and the uniqueness patterns, which are only
+    /// used when assuming a single solution. Subsets, fish and chains take
+    /// longer to look for than guessing does on most puzzles and are added
+    /// with a [`TechniqueGroup`].
+    pub const BASIC: &'static [Technique] = &[
+        Technique::HiddenSingle,
+        Technique::NakedSingle,
+        Technique::CageSum,
+        Technique::Constraint,
+        Technique::Pointing,
+        Technique::BoxLineReduction,
+        Technique::UniqueRectangleType1,
+        Technique::UniqueRectangleType2,
+        Technique::UniqueRectangleType3,
+        Technique::UniqueRectangleType4,
+        Technique::UniqueRectangleType5,
+        Technique::UniqueRectangleType6,
+        Technique::AvoidableRectangle,
+        Technique::BugPlusOne,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Technique::HiddenSingle => "Hidden single",
+            Technique::NakedSingle => "Naked single",
+            Technique::CageSum => "Cage sum",
+            Technique::Constraint => "Constraint",
+            Technique::Pointing => "Pointing",
+            Technique::BoxLineReduction => "Box/line reduction",
+            Technique::NakedPair => "Naked pair",
+            Technique::HiddenPair => "Hidden pair",
+            Technique::NakedTriple => "Naked triple",
+            Technique::HiddenTriple => "Hidden triple",
+            Technique::NakedQuad => "Naked quad",
+            Technique::HiddenQuad => "Hidden quad",
+            Technique::XWing => "X-Wing",
+            Technique::FinnedXWing => "Finned X-Wing",
+            Technique::SashimiXWing => "Sashimi X-Wing",
+            Technique::Swordfish => "Swordfish",
+            Technique::FinnedSwordfish => "Finned Swordfish",
+            Technique::SashimiSwordfish => "Sashimi Swordfish",
+            Technique::Jellyfish => "Jellyfish",
+            Technique::FinnedJellyfish => "Finned Jellyfish",
+            Technique::SashimiJellyfish => "Sashimi Jellyfish",
+            Technique::XYWing => "XY-Wing",
+            Technique::XYZWing => "XYZ-Wing",
+            Technique::WWing => "W-Wing",
+            Technique::SimpleColoring => "Simple coloring",
+            Technique::XChain => "X-Chain",
+            Technique::Aic => "Alternating inference chain",
+            Technique::UniqueRectangleType1 => "Unique rectangle type 1",
+            Technique::UniqueRectangleType2 => "Unique rectangle type 2",
+            Technique::UniqueRectangleType3 => "Unique rectangle type 3",
+            Technique::UniqueRectangleType4 => "Unique rectangle type 4",
+            Technique::UniqueRectangleType5 => "Unique rectangle type 5",
+            Technique::UniqueRectangleType6 => "Unique rectangle type 6",
+            Technique::AvoidableRectangle => "Avoidable rectangle",
+            Technique::BugPlusOne => "BUG+1",
+        }
+    }
+
+    /// How hard the technique is for a human, on a scale similar to the
+    /// Sudoku Explainer rating.
+    pub fn difficulty(&self) -> f32 {
+        match self {
+            Technique::HiddenSingle => 1.5,
+            Technique::NakedSingle => 2.3,
+            Technique::CageSum => 2.5,
+            Technique::Constraint => 2.5,
+            Technique::Pointing => 2.6,
+            Technique::BoxLineReduction => 2.8,
+            Technique::NakedPair => 3.0,
+            Technique::XWing => 3.2,
+            Technique::HiddenPair => 3.4,
+            Technique::FinnedXWing => 3.4,
+            Technique::SashimiXWing => 3.5,
+            Technique::NakedTriple => 3.6,
+            Technique::Swordfish => 3.8,
+            Technique::HiddenTriple => 4.0,
+            Technique::FinnedSwordfish => 4.0,
+            Technique::SashimiSwordfish => 4.1,
+            Technique::XYWing => 4.2,
+            Technique::XYZWing => 4.4,
+            Technique::WWing => 4.4,
+            Technique::UniqueRectangleType1 => 4.5,
+            Technique::UniqueRectangleType2 => 4.5,
+            Technique::UniqueRectangleType3 => 4.5,
+            Technique::UniqueRectangleType4 => 4.5,
+            Technique::UniqueRectangleType5 => 4.5,
+            Technique::UniqueRectangleType6 => 4.5,
+            Technique::AvoidableRectangle => 4.5,
+            Technique::NakedQuad => 5.0,
+            Technique::Jellyfish => 5.2,
+            Technique::HiddenQuad => 5.4,
+            Technique::FinnedJellyfish => 5.4,
+            Technique::SashimiJellyfish => 5.5,
+            Technique::BugPlusOne => 5.6,
+            Technique::SimpleColoring => 6.5,
+            Technique::XChain => 6.6,
+            Technique::Aic => 7.0,
+        }
+    }
+
+    /// Whether the technique relies on the puzzle having a single solution.
+    /// Its deductions can be wrong for puzzles with several.
+    pub fn assumes_uniqueness(&self) -> bool {
+        matches!(
+            self,
+            Technique::UniqueRectangleType1
+                | Technique::UniqueRectangleType2
+                | Technique::UniqueRectangleType3
+                | Technique::UniqueRectangleType4
+                | Technique::UniqueRectangleType5
+                | Technique::UniqueRectangleType6
+                | Technique::AvoidableRectangle
+                | Technique::BugPlusOne
+        )
+    }
+
+    /// Looks for one instance of the technique.
+    pub fn find(&self, candidates: &Candidates) -> Option<Deduction> {
+        match self {
+            Technique::HiddenSingle => basic::hidden_single(candidates),
+            Technique::NakedSingle => basic::naked_single(candidates),
+            Technique::CageSum => cages::cage_sum(candidates),
+            Technique::Constraint => constraints::constraint(candidates),
+            Technique::Pointing => basic::pointing(candidates),
+            Technique::BoxLineReduction => basic::box_line_reduction(candidates),
+            Technique::NakedPair => basic::naked_subset(candidates, *self, 2),
+            Technique::HiddenPair => basic::hidden_subset(candidates, *self, 2),
+            Technique::NakedTriple => basic::naked_subset(candidates, *self, 3),
+            Technique::HiddenTriple => basic::hidden_subset(candidates, *self, 3),
+            Technique::NakedQuad => basic::naked_subset(candidates, *self, 4),
+            Technique::HiddenQuad => basic::hidden_subset(candidates, *self, 4),
+            Technique::XWing => fish::fish(candidates, *self, 2, fish::Kind::Basic),
+            Technique::FinnedXWing => fish::fish(candidates, *self, 2, fish::Kind::Finned),
+            Technique::SashimiXWing => fish::fish(candidates, *self, 2, fish::Kind::Sashimi),
+            Technique::Swordfish => fish::fish(candidates, *self, 3, fish::Kind::Basic),
+            Technique::FinnedSwordfish => fish::fish(candidates, *self, 3, fish::Kind::Finned),
+            Technique::SashimiSwordfish => fish::fish(candidates, *self, 3, fish::Kind::Sashimi),
+            Technique::Jellyfish => fish::fish(candidates, *self, 4, fish::Kind::Basic),
+            Technique::FinnedJellyfish => fish::fish(candidates, *self, 4, fish::Kind::Finned),
+            Technique::SashimiJellyfish => fish::fish(candidates, *self, 4, fish::Kind::Sashimi),
+            Technique::XYWing | Technique::XYZWing => chains::wing(candidates, *self),
+            Technique::WWing => chains::w_wing(candidates),
+            Technique::SimpleColoring => chains::simple_coloring(candidates),
+            Technique::XChain | Technique::Aic => chains::chain(candidates, *self),
+            Technique::UniqueRectangleType1 => uniqueness::unique_rectangle(candidates, *self, 1),
+            Technique::UniqueRectangleType2 => uniqueness::unique_rectangle(candidates, *self, 2),
+            Technique::UniqueRectangleType3 => uniqueness::unique_rectangle(candidates, *self, 3),
+            Technique::UniqueRectangleType4 => uniqueness::unique_rectangle(candidates, *self, 4),
+            Technique::UniqueRectangleType5 => uniqueness::unique_rectangle(candidates, *self, 5),
+            Technique::UniqueRectangleType6 => uniqueness::unique_rectangle(candidates, *self, 6),
+            Technique::AvoidableRectangle => uniqueness::avoidable_rectangle(candidates),
+            Technique::BugPlusOne => uniqueness::bug_plus_one(candidates),
+        }
+    }
+}
+
+impl fmt::Display for Technique {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The techniques beyond [`Technique::BASIC`] that can be turned on
+/// together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TechniqueGroup {
+    /// Naked and hidden pairs, triples and quads.
+    Subsets,
+    /// X-Wings, Swordfish and Jellyfish, with their finned and sashimi forms.
+    Fish,
+    /// Wings, simple coloring, X-Chains and alternating inference chains.
+    Chains,
+    /// Every technique.
+    All,
+}
+
+impl TechniqueGroup {
+    pub fn contains(&self, technique: Technique) -> bool {
+        match self {
+            TechniqueGroup::Subsets => matches!(
+                technique,
+                Technique::NakedPair
+                    | Technique::HiddenPair
+                    | Technique::NakedTriple
+                    | Technique::HiddenTriple
+                    | Technique::NakedQuad
+                    | Technique::HiddenQuad
+            ),
+            TechniqueGroup::Fish => matches!(
+                technique,
+                Technique::XWing
+                    | Technique::FinnedXWing
+                    | Technique::SashimiXWing
+                    | Technique::Swordfish
+                    | Technique::FinnedSwordfish
+                    | Technique::SashimiSwordfish
+                    | Technique::Jellyfish
+                    | Technique::FinnedJellyfish
+                    | Technique::SashimiJellyfish
+            ),
+            TechniqueGroup::Chains => matches!(
+                technique,
+                Technique::XYWing
+                    | Technique::XYZWing
+                    | Technique::WWing
+                    | Technique::SimpleColoring
+                    | Technique::XChain
+                    | Technique::Aic
+            ),
+            TechniqueGroup::All => true,
+        }
+    }
+
+    /// The basic techniques followed by those of `groups`, from the simplest
+    /// to the most complex.
+    pub fn techniques(groups: &[TechniqueGroup]) -> Vec<Technique> {
+        Technique::ALL
+            .iter()
+            .copied()
+            .filter(|&t| Technique::BASIC.contains(&t) || groups.iter().any(|g| g.contains(t)))
+            .collect()
+    }
+}
+
+impl FromStr for TechniqueGroup {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<TechniqueGroup, String> {
+        match name {
+            "subsets" => Ok(TechniqueGroup::Subsets),
+            "fish" => Ok(TechniqueGroup::Fish),
+            "chains" => Ok(TechniqueGroup::Chains),
+            "all" => Ok(TechniqueGroup::All),
+            _ => Err(format!(
+                "unknown techniques '{}', expected subsets, fish, chains or all",
+                name
+            )),
+        }
+    }
+}
+
+/// One logical step: the pattern a technique found and what follows from it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deduction {
+    pub technique: Technique,
+    /// The cells forming the pattern.
+    pub cells: Vec<(i32, i32)>,
+    /// Digits placed, as `(x, y, digit)`.
+    pub placements: Vec<(i32, i32, i32)>,
+    /// Candidates eliminated, as `(x, y, digit)`.
+    pub eliminations: Vec<(i32, i32, i32)>,
+    /// What the pattern is, for example "5 in row 3 only fits r3c7".
+    pub description: String,
+}
+
+impl Deduction {
+    /// The deduction found in grid `g` of several overlapping ones, moved to
+    /// where that grid starts. The description keeps the cell names of the
+    /// grid.
+    pub fn in_grid(self, g: usize, (left, top): (i32, i32)) -> Deduction {
+        let moved = |&(x, y, digit): &(i32, i32, i32)| (left + x, top + y, digit);
+        Deduction {
+            technique: self.technique,
+            cells: self
+                .cells
+                .iter()
+                .map(|&(x, y)| (left + x, top + y))
+                .collect(),
+            placements: self.placements.iter().map(moved).collect(),
+            eliminations: self.eliminations.iter().map(moved).collect(),
+            description: format!("in grid {}, {}", g + 1, self.description),
+        }
+    }
+}
+
+impl fmt::Display for Deduction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.technique, self.description)?;
+        let effects = self
+            .placements
+            .iter()
+            .map(|&(x, y, digit)| format!("{}={}", cell_name(x, y), digit))
+            .chain(
+                self.eliminations
+                    .iter()
+                    .map(|&(x, y, digit)| format!("{}<>{}", cell_name(x, y), digit)),
+            )
+            .collect::<Vec<String>>();
+        if !effects.is_empty() {
+            write!(f, " => {}", effects.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Every way of choosing `k` of the items, in order.
+fn combinations<T: Copy>(items: &[T], k: usize) -> Vec<Vec<T>> {
+    if k == 0 {
+        return vec![Vec::new()];
+    }
+    let mut result = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        if items.len() - i < k {
+            break;
+        }
+        for mut rest in combinations(&items[i + 1..], k - 1) {
+            rest.insert(0, *item);
+            result.push(rest);
+        }
+    }
+    result
+}
+
+/// Eliminates `digit` from the open cells that see all of `cells`.
+fn eliminate_seeing_all(
+    candidates: &Candidates,
+    digit: i32,
+    cells: &[(i32, i32)],
+) -> Vec<(i32, i32, i32)> {
+    let (first_x, first_y) = cells[0];
+    let houses = candidates.houses_of(first_x, first_y).iter();
+    let peers = houses
+        .flat_map(|&house| candidates.house_cells(house))
+        .chain(
+            candidates
+                .rules()
+                .extra_peers(first_x, first_y)
+                .iter()
+                .copied(),
+        );
+    let mut eliminations = Vec::new();
+    for (x, y) in peers {
+        if candidates.is_open(x, y, digit)
+            && cells.iter().all(|&other| candidates.sees((x, y), other))
+            && !eliminations.contains(&(x, y, digit))
+        {
+            eliminations.push((x, y, digit));
+        }
+    }
+    eliminations
+}
+
+fn cell_list(cells: &[(i32, i32)]) -> String {
+    cells
+        .iter()
+        .map(|&(x, y)| cell_name(x, y))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn digit_list(mask: u32) -> String {
+    digits(mask)
+        .iter()
+        .map(|digit| digit.to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// The digits in a candidate mask.
+fn digits(mask: u32) -> Vec<i32> {
+    (1..=Shape::MAX_SIZE)
+        .filter(|digit| mask & (1 << (digit - 1)) != 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rating;
+    use crate::reader::{self, Format};
+
+    #[test]
+    fn layouts_are_refused() {
+        let puzzle = reader::read(
+            include_str!("../testdata/variants").as_bytes(),
+            Format::Auto,
+        )
+        .map(Result::unwrap)
+        .find(|puzzle| puzzle.layout.is_some())
+        .unwrap();
+        assert_eq!(hint(&puzzle, false).err(), Some(PuzzleError::Layout));
+        assert_eq!(
+            rating::rate(&puzzle, false).err(),
+            Some(PuzzleError::Layout)
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Singles, naked and hidden subsets, and intersections between blocks and
+//! lines.
+
+use super::{cell_list, combinations, digit_list, digits, Candidates, Deduction, Technique};
+use crate::house::{cell_name, House};
+
+fn is_placed_in(candidates: &Candidates, house: House, digit: i32) -> bool {
+    candidates
+        .house_cells(house)
+        .any(|(x, y)| candidates.is_placed(x, y) && candidates.cell(x, y).solution() == Some(digit))
+}
+
+pub fn hidden_single(candidates: &Candidates) -> Option<Deduction> {
+    for house in candidates.houses() {
+        for digit in 1..=candidates.size() {
+            let positions = candidates.positions(house, digit);
+            if positions.len() != 1 || is_placed_in(candidates, house, digit) {
+                continue;
+            }
+            let (x, y) = positions[0];
+            return Some(Deduction {
+                technique: Technique::HiddenSingle,
+                cells: positions,
+                placements: vec![(x, y, digit)],
+                eliminations: Vec::new(),
+                description: format!("{} in {} only fits {}", digit, house, cell_name(x, y)),
+            });
+        }
+    }
+    None
+}
+
+pub fn naked_single(candidates: &Candidates) -> Option<Deduction> {
+    let shape = candidates.shape();
+    for i in 0..shape.num_cells() {
+        let (x, y) = shape.cell(i);
+        if candidates.is_placed(x, y) {
+            continue;
+        }
+        if let Some(digit) = candidates.cell(x, y).solution() {
+            return Some(Deduction {
+                technique: Technique::NakedSingle,
+                cells: vec![(x, y)],
+                placements: vec![(x, y, digit)],
+                eliminations: Vec::new(),
+                description: format!("{} can only be {}", cell_name(x, y), digit),
+            });
+        }
+    }
+    None
+}
+
+/// Eliminates `digit` from the open cells of `house` that are not in
+/// `except`.
+fn eliminate_outside(
+    candidates: &Candidates,
+    house: House,
+    digit: i32,
+    except: &[(i32, i32)],
+) -> Vec<(i32, i32, i32)> {
+    candidates
+        .house_cells(house)
+        .filter(|cell| !except.contains(cell))
+        .filter(|&(x, y)| candidates.is_open(x, y, digit))
+        .map(|(x, y)| (x, y, digit))
+        .collect()
+}
+
+/// Finds a digit whose positions in one of `houses` all lie in a single
+/// house of another kind, and eliminates it from the rest of that house.
+fn intersection(
+    candidates: &Candidates,
+    technique: Technique,
+    houses: &[House],
+    other_houses: fn(&Candidates, i32, i32) -> Vec<House>,
+) -> Option<Deduction> {
+    let rules = candidates.rules();
+    for &house in houses {
+        for digit in 1..=candidates.size() {
+            let positions = candidates.positions(house, digit);
+            if positions.len() < 2 {
+                continue;
+            }
+            let (first_x, first_y) = positions[0];
+            for other in other_houses(candidates, first_x, first_y) {
+                if !positions.iter().all(|&(x, y)| rules.contains(other, x, y)) {
+                    continue;
+                }
+                let eliminations = eliminate_outside(candidates, other, digit, &positions);
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique,
+                        cells: positions,
+                        placements: Vec::new(),
+                        eliminations,
+                        description: format!("{} in {} is confined to {}", digit, house, other),
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// A digit confined to one row or column of a block can be eliminated from
+/// the rest of that line.
+pub fn pointing(candidates: &Candidates) -> Option<Deduction> {
+    let blocks: Vec<House> = (0..candidates.size()).map(House::Block).collect();
+    intersection(candidates, Technique::Pointing, &blocks, |_, x, y| {
+        vec![House::Row(y), House::Column(x)]
+    })
+}
+
+/// A digit confined to one block of a row or column can be eliminated from
+/// the rest of that block.
+pub fn box_line_reduction(candidates: &Candidates) -> Option<Deduction> {
+    let size = candidates.size();
+    let lines: Vec<House> = (0..size)
+        .map(House::Row)
+        .chain((0..size).map(House::Column))
+        .collect();
+    intersection(
+        candidates,
+        Technique::BoxLineReduction,
+        &lines,
+        |candidates, x, y| vec![House::Block(candidates.rules().block(x, y))],
+    )
+}
+
+/// `size` cells of a house that together only have `size` candidates. Those
+/// digits can be eliminated from the rest of the house.
+pub fn naked_subset(
+    candidates: &Candidates,
+    technique: Technique,
+    size: usize,
+) -> Option<Deduction> {
+    for house in candidates.houses() {
+        let open: Vec<(i32, i32)> = candidates
+            .house_cells(house)
+            .filter(|&(x, y)| !candidates.is_placed(x, y))
+            .collect();
+        if open.len() <= size {
+            continue;
+        }
+        let small: Vec<(i32, i32)> = open
+            .iter()
+            .copied()
+            .filter(|&(x, y)| (2..=size).contains(&candidates.cell(x, y).num_possibles()))
+            .collect();
+        for subset in combinations(&small, size) {
+            let mask = subset.iter().fold(0, |mask, &(x, y)| {
+                mask | candidates.cell(x, y).possibles_mask()
+            });
+            if mask.count_ones() as usize != size {
+                continue;
+            }
+            let eliminations: Vec<(i32, i32, i32)> = digits(mask)
+                .into_iter()
+                .flat_map(|digit| eliminate_outside(candidates, house, digit, &subset))
+                .collect();
+            if !eliminations.is_empty() {
+                return Some(Deduction {
+                    technique,
+                    description: format!(
+                        "{} only contain {} in {}",
+                        cell_list(&subset),
+                        digit_list(mask),
+                        house
+                    ),
+                    cells: subset,
+                    placements: Vec::new(),
+                    eliminations,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// `size` digits that only fit in `size` cells of a house. Other digits can
+/// be eliminated from those cells.
+pub fn hidden_subset(
+    candidates: &Candidates,
+    technique: Technique,
+    size: usize,
+) -> Option<Deduction> {
+    for house in candidates.houses() {
+        let open_digits: Vec<(i32, Vec<(i32, i32)>)> = (1..=candidates.size())
+            .map(|digit| (digit, candidates.positions(house, digit)))
+            .filter(|(_, positions)| (2..=size).contains(&positions.len()))
+            .collect();
+        for subset in combinations(&(0..open_digits.len()).collect::<Vec<usize>>(), size) {
+            let mut cells: Vec<(i32, i32)> = Vec::new();
+            let mut mask = 0;
+            for &i in &subset {
+                let (digit, positions) = &open_digits[i];
+                mask |= 1 << (digit - 1);
+                for cell in positions {
+                    if !cells.contains(cell) {
+                        cells.push(*cell);
+                    }
+                }
+            }
+            if cells.len() != size {
+                continue;
+            }
+            cells.sort_by_key(|&(x, y)| (y, x));
+            let eliminations: Vec<(i32, i32, i32)> = cells
+                .iter()
+                .flat_map(|&(x, y)| {
+                    digits(candidates.cell(x, y).possibles_mask() & !mask)
+                        .into_iter()
+                        .map(move |digit| (x, y, digit))
+                })
+                .collect();
+            if !eliminations.is_empty() {
+                return Some(Deduction {
+                    technique,
+                    description: format!(
+                        "{} only fit {} in {}",
+                        digit_list(mask),
+                        cell_list(&cells),
+                        house
+                    ),
+                    cells,
+                    placements: Vec::new(),
+                    eliminations,
+                });
+            }
+        }
+    }
+    None
+}
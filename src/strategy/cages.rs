@@ -0,0 +1,53 @@
+//! Sum combinations of killer cages.
+
+use super::{cell_list, digit_list, digits, Candidates, Deduction, Technique};
+
+/// Digits of a cage cell that are in no combination of different digits
+/// adding up to the sum of the cage can be eliminated.
+pub fn cage_sum(candidates: &Candidates) -> Option<Deduction> {
+    let rules = candidates.rules();
+    for (i, cage) in rules.cages().iter().enumerate() {
+        let possibles: Vec<u32> = cage
+            .cells
+            .iter()
+            .map(|&(x, y)| candidates.cell(x, y).possibles_mask())
+            .collect();
+        let allowed = rules.cage_possibles(i, &possibles);
+        // A cage that cannot add up to its sum is left to the solver.
+        if allowed.contains(&0) {
+            continue;
+        }
+        let eliminations: Vec<(i32, i32, i32)> = cage
+            .cells
+            .iter()
+            .zip(possibles.iter().zip(&allowed))
+            .filter(|(&(x, y), _)| !candidates.is_placed(x, y))
+            .flat_map(|(&(x, y), (&possible, &allowed))| {
+                digits(possible & !allowed)
+                    .into_iter()
+                    .map(move |digit| (x, y, digit))
+            })
+            .collect();
+        if !eliminations.is_empty() {
+            let union = allowed.iter().fold(0, |union, mask| union | mask);
+            let description = if cage.cells.len() == 1 {
+                format!("{} is a cage of {}", cell_list(&cage.cells), cage.sum)
+            } else {
+                format!(
+                    "{} add up to {} with {}",
+                    cell_list(&cage.cells),
+                    cage.sum,
+                    digit_list(union)
+                )
+            };
+            return Some(Deduction {
+                technique: Technique::CageSum,
+                description,
+                cells: cage.cells.clone(),
+                placements: Vec::new(),
+                eliminations,
+            });
+        }
+    }
+    None
+}
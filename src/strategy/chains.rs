@@ -0,0 +1,496 @@
+//! Wings, single digit coloring and alternating inference chains.
+//!
+//! A strong link between two candidates means at least one of them is true,
+//! like the only two positions of a digit in a house or the two digits of a
+//! cell with two candidates. A weak link means at most one of them is true,
+//! like the same digit in two cells that see each other. Chains alternate
+//! between the two, so that if the first candidate is false the last one is
+//! true.
+
+use std::collections::VecDeque;
+
+use super::{cell_list, eliminate_seeing_all, Candidates, Deduction, Technique};
+use crate::house::cell_name;
+use crate::rules::CellSet;
+
+fn unplaced_cells(candidates: &Candidates) -> Vec<(i32, i32)> {
+    let shape = candidates.shape();
+    (0..shape.num_cells())
+        .map(|i| shape.cell(i))
+        .filter(|&(x, y)| !candidates.is_placed(x, y))
+        .collect()
+}
+
+/// The digit shared by two candidate masks with exactly one in common.
+fn common_digit(a: u32, b: u32) -> Option<i32> {
+    let common = a & b;
+    if common.count_ones() == 1 {
+        Some(common.trailing_zeros() as i32 + 1)
+    } else {
+        None
+    }
+}
+
+/// A pivot with candidates xy (or xyz) seeing two pincers xz and yz. One of
+/// the pincers (or the pivot of an XYZ-Wing) is z, so z can be eliminated
+/// from the cells seeing all of them.
+pub fn wing(candidates: &Candidates, technique: Technique) -> Option<Deduction> {
+    let pivot_size = if technique == Technique::XYZWing {
+        3
+    } else {
+        2
+    };
+    let cells = unplaced_cells(candidates);
+    let bivalue: Vec<(i32, i32)> = cells
+        .iter()
+        .copied()
+        .filter(|&(x, y)| candidates.cell(x, y).num_possibles() == 2)
+        .collect();
+    for &pivot in &cells {
+        let pivot_mask = candidates.cell(pivot.0, pivot.1).possibles_mask();
+        if pivot_mask.count_ones() != pivot_size {
+            continue;
+        }
+        let pincers: Vec<(i32, i32)> = bivalue
+            .iter()
+            .copied()
+            .filter(|&pincer| candidates.sees(pivot, pincer))
+            .collect();
+        for (i, &first) in pincers.iter().enumerate() {
+            for &second in &pincers[i + 1..] {
+                let first_mask = candidates.cell(first.0, first.1).possibles_mask();
+                let second_mask = candidates.cell(second.0, second.1).possibles_mask();
+                let z = match common_digit(first_mask, second_mask) {
+                    Some(z) => z,
+                    None => continue,
+                };
+                // The pincers of an XY-Wing add z to the pivot, those of an
+                // XYZ-Wing only have digits of the pivot.
+                let valid = if pivot_size == 2 {
+                    pivot_mask & (1 << (z - 1)) == 0
+                        && (first_mask | second_mask) == pivot_mask | (1 << (z - 1))
+                } else {
+                    (first_mask | second_mask) == pivot_mask
+                };
+                if !valid {
+                    continue;
+                }
+                let pattern = if pivot_size == 2 {
+                    vec![first, second]
+                } else {
+                    vec![pivot, first, second]
+                };
+                let eliminations = eliminate_seeing_all(candidates, z, &pattern);
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique,
+                        cells: vec![pivot, first, second],
+                        placements: Vec::new(),
+                        eliminations,
+                        description: format!(
+                            "pivot {} with pincers {} and {}, one of which is {}",
+                            cell_name(pivot.0, pivot.1),
+                            cell_name(first.0, first.1),
+                            cell_name(second.0, second.1),
+                            z
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Two cells with the same two candidates xy that do not see each other,
+/// connected by a strong link on x. One of them is y, so y can be
+/// eliminated from cells seeing both.
+pub fn w_wing(candidates: &Candidates) -> Option<Deduction> {
+    let bivalue: Vec<(i32, i32)> = unplaced_cells(candidates)
+        .into_iter()
+        .filter(|&(x, y)| candidates.cell(x, y).num_possibles() == 2)
+        .collect();
+    for (i, &first) in bivalue.iter().enumerate() {
+        let mask = candidates.cell(first.0, first.1).possibles_mask();
+        for &second in &bivalue[i + 1..] {
+            if candidates.sees(first, second)
+                || candidates.cell(second.0, second.1).possibles_mask() != mask
+            {
+                continue;
+            }
+            let digits = candidates.cell(first.0, first.1).possibles();
+            for &(x, y) in &[(digits[0], digits[1]), (digits[1], digits[0])] {
+                for house in candidates.houses() {
+                    let positions = candidates.positions(house, x);
+                    if positions.len() != 2
+                        || positions.contains(&first)
+                        || positions.contains(&second)
+                    {
+                        continue;
+                    }
+                    let (p, q) = if candidates.sees(positions[0], first)
+                        && candidates.sees(positions[1], second)
+                    {
+                        (positions[0], positions[1])
+                    } else if candidates.sees(positions[1], first)
+                        && candidates.sees(positions[0], second)
+                    {
+                        (positions[1], positions[0])
+                    } else {
+                        continue;
+                    };
+                    let eliminations = eliminate_seeing_all(candidates, y, &[first, second]);
+                    if !eliminations.is_empty() {
+                        return Some(Deduction {
+                            technique: Technique::WWing,
+                            cells: vec![first, p, q, second],
+                            placements: Vec::new(),
+                            eliminations,
+                            description: format!(
+                                "{} and {} are both {}/{}, linked by the strong link on {} between {} and {} in {}",
+                                cell_name(first.0, first.1),
+                                cell_name(second.0, second.1),
+                                x.min(y),
+                                x.max(y),
+                                x,
+                                cell_name(p.0, p.1),
+                                cell_name(q.0, q.1),
+                                house
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Colors the cells connected by strong links on a digit in two colors, one
+/// of which is true. A color with two cells seeing each other is false, and
+/// cells seeing both colors cannot hold the digit.
+pub fn simple_coloring(candidates: &Candidates) -> Option<Deduction> {
+    for digit in 1..=candidates.size() {
+        let mut links: Vec<((i32, i32), (i32, i32))> = Vec::new();
+        for house in candidates.houses() {
+            let positions = candidates.positions(house, digit);
+            if positions.len() == 2 && !links.contains(&(positions[0], positions[1])) {
+                links.push((positions[0], positions[1]));
+            }
+        }
+        let mut colored: Vec<(i32, i32)> = Vec::new();
+        for &(start, _) in &links {
+            if colored.contains(&start) {
+                continue;
+            }
+            // Breadth first over the strong links, alternating colors.
+            let mut colors: Vec<((i32, i32), bool)> = vec![(start, true)];
+            let mut queue = VecDeque::from(vec![(start, true)]);
+            while let Some((cell, color)) = queue.pop_front() {
+                for &(a, b) in &links {
+                    let next = if a == cell {
+                        b
+                    } else if b == cell {
+                        a
+                    } else {
+                        continue;
+                    };
+                    if !colors.iter().any(|&(c, _)| c == next) {
+                        colors.push((next, !color));
+                        queue.push_back((next, !color));
+                    }
+                }
+            }
+            colored.extend(colors.iter().map(|&(c, _)| c));
+            if colors.len() < 3 {
+                continue;
+            }
+            let of_color = |color: bool| -> Vec<(i32, i32)> {
+                colors
+                    .iter()
+                    .filter(|&&(_, c)| c == color)
+                    .map(|&(cell, _)| cell)
+                    .collect()
+            };
+            let (on, off) = (of_color(true), of_color(false));
+            for cells in [&on, &off].iter() {
+                let wrapped = cells
+                    .iter()
+                    .any(|&a| cells.iter().any(|&b| candidates.sees(a, b)));
+                if wrapped {
+                    return Some(Deduction {
+                        technique: Technique::SimpleColoring,
+                        cells: on.iter().chain(off.iter()).copied().collect(),
+                        placements: Vec::new(),
+                        eliminations: cells.iter().map(|&(x, y)| (x, y, digit)).collect(),
+                        description: format!(
+                            "{} in {} or {}, and two of {} see each other",
+                            digit,
+                            cell_list(&on),
+                            cell_list(&off),
+                            cell_list(cells)
+                        ),
+                    });
+                }
+            }
+            let eliminations: Vec<(i32, i32, i32)> = unplaced_cells(candidates)
+                .into_iter()
+                .filter(|&(x, y)| candidates.cell(x, y).is_possible(digit))
+                .filter(|cell| !on.contains(cell) && !off.contains(cell))
+                .filter(|&cell| {
+                    on.iter().any(|&c| candidates.sees(cell, c))
+                        && off.iter().any(|&c| candidates.sees(cell, c))
+                })
+                .map(|(x, y)| (x, y, digit))
+                .collect();
+            if !eliminations.is_empty() {
+                return Some(Deduction {
+                    technique: Technique::SimpleColoring,
+                    cells: on.iter().chain(off.iter()).copied().collect(),
+                    placements: Vec::new(),
+                    eliminations,
+                    description: format!(
+                        "{} is either in {} or in {}",
+                        digit,
+                        cell_list(&on),
+                        cell_list(&off)
+                    ),
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Longest chain searched, in links.
+const MAX_CHAIN_LINKS: usize = 12;
+
+/// A candidate in a chain, `cell * size + digit - 1` with cells numbered
+/// in row-major order.
+type Node = usize;
+
+/// The strong and weak links between the open candidates. Links within a
+/// house keep to one digit, so chains without the links within cells are
+/// X-Chains.
+struct Links {
+    size: usize,
+    strong: Vec<Vec<Node>>,
+    weak: Vec<Vec<Node>>,
+    /// The cells where a digit is open, indexed by `digit - 1`.
+    open: Vec<CellSet>,
+    /// The cells each cell sees.
+    peers: Vec<CellSet>,
+}
+
+impl Links {
+    fn new(candidates: &Candidates, within_cells: bool) -> Links {
+        let shape = candidates.shape();
+        let size = shape.size() as usize;
+        let num_nodes = shape.num_cells() * size;
+        let mut links = Links {
+            size,
+            strong: vec![Vec::new(); num_nodes],
+            weak: vec![Vec::new(); num_nodes],
+            open: Vec::new(),
+            peers: Vec::new(),
+        };
+        let (strong, weak) = (&mut links.strong, &mut links.weak);
+        let node =
+            |cell: (i32, i32), digit: i32| shape.index(cell.0, cell.1) * size + digit as usize - 1;
+        for digit in 1..=candidates.size() {
+            for house in candidates.houses() {
+                let positions = candidates.positions(house, digit);
+                for (i, &a) in positions.iter().enumerate() {
+                    for &b in &positions[i + 1..] {
+                        let (a, b) = (node(a, digit), node(b, digit));
+                        if positions.len() == 2 {
+                            strong[a].push(b);
+                            strong[b].push(a);
+                        }
+                        if !weak[a].contains(&b) {
+                            weak[a].push(b);
+                            weak[b].push(a);
+                        }
+                    }
+                }
+            }
+        }
+        if within_cells {
+            for cell in unplaced_cells(candidates) {
+                let possibles = candidates.cell(cell.0, cell.1).possibles();
+                for (i, &a) in possibles.iter().enumerate() {
+                    for &b in &possibles[i + 1..] {
+                        let (a, b) = (node(cell, a), node(cell, b));
+                        if possibles.len() == 2 {
+                            strong[a].push(b);
+                            strong[b].push(a);
+                        }
+                        weak[a].push(b);
+                        weak[b].push(a);
+                    }
+                }
+            }
+        }
+        let cells: Vec<(i32, i32)> = (0..shape.num_cells()).map(|i| shape.cell(i)).collect();
+        let mask = |keep: &dyn Fn((i32, i32)) -> bool| {
+            let mut mask = CellSet::new();
+            for (i, &cell) in cells.iter().enumerate() {
+                if keep(cell) {
+                    mask.set(i, true);
+                }
+            }
+            mask
+        };
+        links.open = (1..=candidates.size())
+            .map(|digit| mask(&|(x, y)| candidates.is_open(x, y, digit)))
+            .collect();
+        links.peers = cells
+            .iter()
+            .map(|&(x, y)| *candidates.rules().peers(x, y))
+            .collect();
+        links
+    }
+
+    fn node_cell(&self, candidates: &Candidates, node: Node) -> (i32, i32) {
+        candidates.shape().cell(node / self.size)
+    }
+
+    fn node_digit(&self, node: Node) -> i32 {
+        (node % self.size) as i32 + 1
+    }
+
+    fn node_name(&self, candidates: &Candidates, node: Node) -> String {
+        let (x, y) = self.node_cell(candidates, node);
+        format!("{}({})", cell_name(x, y), self.node_digit(node))
+    }
+}
+
+/// Placements and eliminations, as `(x, y, digit)`.
+type Effects = (Vec<(i32, i32, i32)>, Vec<(i32, i32, i32)>);
+
+/// What follows from a chain where either `start` or `end` is true.
+fn chain_conclusion(candidates: &Candidates, links: &Links, start: Node, end: Node) -> Effects {
+    let (start_cell, start_digit) = (links.node_cell(candidates, start), links.node_digit(start));
+    let (end_cell, end_digit) = (links.node_cell(candidates, end), links.node_digit(end));
+    if start == end {
+        // Assuming the candidate false proves it true.
+        return (vec![(start_cell.0, start_cell.1, start_digit)], Vec::new());
+    }
+    let eliminations = if start_digit == end_digit {
+        let seeing_both = links.open[start_digit as usize - 1]
+            & links.peers[start / links.size]
+            & links.peers[end / links.size];
+        seeing_both
+            .into_iter()
+            .map(|i| {
+                let (x, y) = candidates.shape().cell(i);
+                (x, y, start_digit)
+            })
+            .collect()
+    } else if start_cell == end_cell {
+        candidates
+            .cell(start_cell.0, start_cell.1)
+            .possibles()
+            .into_iter()
+            .filter(|&digit| digit != start_digit && digit != end_digit)
+            .map(|digit| (start_cell.0, start_cell.1, digit))
+            .collect()
+    } else if candidates.sees(start_cell, end_cell) {
+        let mut eliminations = Vec::new();
+        if candidates
+            .cell(start_cell.0, start_cell.1)
+            .is_possible(end_digit)
+        {
+            eliminations.push((start_cell.0, start_cell.1, end_digit));
+        }
+        if candidates
+            .cell(end_cell.0, end_cell.1)
+            .is_possible(start_digit)
+        {
+            eliminations.push((end_cell.0, end_cell.1, start_digit));
+        }
+        eliminations
+    } else {
+        Vec::new()
+    };
+    (Vec::new(), eliminations)
+}
+
+/// Searches for the shortest alternating chain starting and ending with a
+/// strong link that places or eliminates something. An X-Chain only uses
+/// links on a single digit.
+pub fn chain(candidates: &Candidates, technique: Technique) -> Option<Deduction> {
+    let links = Links::new(candidates, technique == Technique::Aic);
+    (0..links.strong.len())
+        .filter(|&start| !links.strong[start].is_empty())
+        .find_map(|start| chain_from(candidates, technique, &links, start))
+}
+
+fn chain_from(
+    candidates: &Candidates,
+    technique: Technique,
+    links: &Links,
+    start: Node,
+) -> Option<Deduction> {
+    // States are a node and whether it was reached by a strong link, which
+    // makes it true if the start is false.
+    let state = |node: Node, strong: bool| node * 2 + strong as usize;
+    let mut parent: Vec<Option<usize>> = vec![None; links.strong.len() * 2];
+    let mut depth = vec![0; links.strong.len() * 2];
+    let mut queue = VecDeque::from(vec![state(start, false)]);
+    parent[state(start, false)] = Some(state(start, false));
+    while let Some(current) = queue.pop_front() {
+        let (current_node, reached_by_strong) = (current / 2, current % 2 == 1);
+        if depth[current] >= MAX_CHAIN_LINKS {
+            continue;
+        }
+        let next_nodes = if reached_by_strong {
+            &links.weak[current_node]
+        } else {
+            &links.strong[current_node]
+        };
+        for &next_node in next_nodes {
+            let next = state(next_node, !reached_by_strong);
+            if parent[next].is_some() {
+                continue;
+            }
+            parent[next] = Some(current);
+            depth[next] = depth[current] + 1;
+            queue.push_back(next);
+            if reached_by_strong || depth[next] < 3 {
+                continue;
+            }
+            let (placements, eliminations) = chain_conclusion(candidates, links, start, next_node);
+            if placements.is_empty() && eliminations.is_empty() {
+                continue;
+            }
+            let mut nodes = vec![next_node];
+            let mut at = next;
+            while at != state(start, false) {
+                at = parent[at].unwrap();
+                nodes.push(at / 2);
+            }
+            nodes.reverse();
+            let mut description = links.node_name(candidates, nodes[0]);
+            for (i, &n) in nodes.iter().enumerate().skip(1) {
+                description += if i % 2 == 1 { " = " } else { " - " };
+                description += &links.node_name(candidates, n);
+            }
+            let mut cells: Vec<(i32, i32)> = Vec::new();
+            for &n in &nodes {
+                let cell = links.node_cell(candidates, n);
+                if !cells.contains(&cell) {
+                    cells.push(cell);
+                }
+            }
+            return Some(Deduction {
+                technique,
+                cells,
+                placements,
+                eliminations,
+                description,
+            });
+        }
+    }
+    None
+}
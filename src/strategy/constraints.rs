@@ -0,0 +1,39 @@
+//! Eliminations by the constraints of arithmetic variants.
+
+use super::{digits, Candidates, Deduction, Technique};
+
+/// Digits of a cell that a constraint rules out can be eliminated.
+pub fn constraint(candidates: &Candidates) -> Option<Deduction> {
+    for constraint in candidates.rules().constraints() {
+        let cells = constraint.cells();
+        let possibles: Vec<u32> = cells
+            .iter()
+            .map(|&(x, y)| candidates.cell(x, y).possibles_mask())
+            .collect();
+        // A constraint that cannot be met is left to the solver.
+        let allowed = match constraint.prune(candidates.size(), &possibles) {
+            Ok(allowed) => allowed,
+            Err(_) => continue,
+        };
+        let eliminations: Vec<(i32, i32, i32)> = cells
+            .iter()
+            .zip(possibles.iter().zip(&allowed))
+            .filter(|(&(x, y), _)| !candidates.is_placed(x, y))
+            .flat_map(|(&(x, y), (&possible, &allowed))| {
+                digits(possible & !allowed)
+                    .into_iter()
+                    .map(move |digit| (x, y, digit))
+            })
+            .collect();
+        if !eliminations.is_empty() {
+            return Some(Deduction {
+                technique: Technique::Constraint,
+                description: constraint.to_string(),
+                cells: cells.to_vec(),
+                placements: Vec::new(),
+                eliminations,
+            });
+        }
+    }
+    None
+}
@@ -0,0 +1,268 @@
+//! X-Wing, Swordfish and Jellyfish, plain and with fins.
+//!
+//! A fish of size n on a digit has n base lines (rows or columns) whose
+//! positions for the digit all lie in n cover lines of the other direction.
+//! The digit then fills each cover line inside the base lines and can be
+//! eliminated from the rest of the cover lines. Fins are extra positions in
+//! the base lines confined to one block: either a fin is true or the fish
+//! holds, so only cells that are also in the fin block are eliminated. A
+//! sashimi fish is a finned fish that would not even be a fish without the
+//! fins, because a base line has a single position left in the cover lines.
+
+use super::{cell_list, combinations, Candidates, Deduction, Technique};
+use crate::house::House;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Basic,
+    Finned,
+    Sashimi,
+}
+
+fn line(by_rows: bool, i: i32) -> House {
+    if by_rows {
+        House::Row(i)
+    } else {
+        House::Column(i)
+    }
+}
+
+/// The cell where base line `i` crosses cover line `j`.
+fn crossing(by_rows: bool, i: i32, j: i32) -> (i32, i32) {
+    if by_rows {
+        (j, i)
+    } else {
+        (i, j)
+    }
+}
+
+fn indices(mask: u32) -> Vec<i32> {
+    (0..32).filter(|i| mask & (1 << i) != 0).collect()
+}
+
+fn line_list(by_rows: bool, lines: &[i32]) -> String {
+    let names = lines
+        .iter()
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+    if by_rows {
+        format!("rows {}", names)
+    } else {
+        format!("columns {}", names)
+    }
+}
+
+pub fn fish(
+    candidates: &Candidates,
+    technique: Technique,
+    size: usize,
+    kind: Kind,
+) -> Option<Deduction> {
+    let size_of_grid = candidates.size();
+    for digit in 1..=size_of_grid {
+        for &by_rows in &[true, false] {
+            // Bit j of masks[i] is set if the digit is open where line i
+            // crosses line j.
+            let masks: Vec<u32> = (0..size_of_grid)
+                .map(|i| {
+                    candidates
+                        .positions(line(by_rows, i), digit)
+                        .iter()
+                        .fold(0, |mask, &(x, y)| mask | 1 << if by_rows { x } else { y })
+                })
+                .collect();
+            // Fins share a block, so they add at most as many lines as the
+            // widest block crosses.
+            let span = fin_groups(candidates, by_rows)
+                .iter()
+                .map(|group| group.count_ones() as usize)
+                .max()
+                .unwrap_or(0);
+            let max_positions = if kind == Kind::Basic {
+                size
+            } else {
+                size + span
+            };
+            let lines: Vec<i32> = (0..size_of_grid)
+                .filter(|&i| {
+                    (1..=max_positions).contains(&(masks[i as usize].count_ones() as usize))
+                })
+                .collect();
+            for base in combinations(&lines, size) {
+                let union = base.iter().fold(0, |union, &i| union | masks[i as usize]);
+                let found = if kind == Kind::Basic {
+                    if union.count_ones() as usize != size {
+                        continue;
+                    }
+                    find_basic(candidates, technique, digit, by_rows, &base, union)
+                } else {
+                    if union.count_ones() as usize <= size
+                        || union.count_ones() as usize > size + span
+                    {
+                        continue;
+                    }
+                    find_finned(
+                        candidates, technique, kind, digit, by_rows, &base, &masks, union,
+                    )
+                };
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The cover lines crossing each block, as masks without repeats.
+fn fin_groups(candidates: &Candidates, by_rows: bool) -> Vec<u32> {
+    let mut groups = Vec::new();
+    for block in (0..candidates.size()).map(House::Block) {
+        let group = candidates
+            .house_cells(block)
+            .fold(0, |mask, (x, y)| mask | 1 << if by_rows { x } else { y });
+        if !groups.contains(&group) {
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+fn find_basic(
+    candidates: &Candidates,
+    technique: Technique,
+    digit: i32,
+    by_rows: bool,
+    base: &[i32],
+    cover: u32,
+) -> Option<Deduction> {
+    let cover = indices(cover);
+    let eliminations: Vec<(i32, i32, i32)> = cover
+        .iter()
+        .flat_map(|&j| (0..candidates.size()).map(move |i| (i, j)))
+        .filter(|(i, _)| !base.contains(i))
+        .map(|(i, j)| crossing(by_rows, i, j))
+        .filter(|&(x, y)| candidates.is_open(x, y, digit))
+        .map(|(x, y)| (x, y, digit))
+        .collect();
+    if eliminations.is_empty() {
+        return None;
+    }
+    let cells: Vec<(i32, i32)> = base
+        .iter()
+        .flat_map(|&i| cover.iter().map(move |&j| crossing(by_rows, i, j)))
+        .filter(|&(x, y)| candidates.is_open(x, y, digit))
+        .collect();
+    Some(Deduction {
+        technique,
+        description: format!(
+            "{} in {} only fits {}",
+            digit,
+            line_list(by_rows, base),
+            line_list(!by_rows, &cover)
+        ),
+        cells,
+        placements: Vec::new(),
+        eliminations,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn find_finned(
+    candidates: &Candidates,
+    technique: Technique,
+    kind: Kind,
+    digit: i32,
+    by_rows: bool,
+    base: &[i32],
+    masks: &[u32],
+    union: u32,
+) -> Option<Deduction> {
+    // The fins share a block, so the cover lines outside the lines of that
+    // block are fixed and only the rest are chosen among those.
+    for group_mask in fin_groups(candidates, by_rows) {
+        let outside = union & !group_mask;
+        let num_outside = outside.count_ones() as usize;
+        if num_outside > base.len() {
+            continue;
+        }
+        for chosen in combinations(&indices(union & group_mask), base.len() - num_outside) {
+            let cover_mask = chosen.iter().fold(outside, |mask, &j| mask | 1 << j);
+            if let Some(found) = find_finned_with_cover(
+                candidates, technique, kind, digit, by_rows, base, masks, cover_mask,
+            ) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+#[allow(clippy::too_many_arguments)]
+fn find_finned_with_cover(
+    candidates: &Candidates,
+    technique: Technique,
+    kind: Kind,
+    digit: i32,
+    by_rows: bool,
+    base: &[i32],
+    masks: &[u32],
+    cover_mask: u32,
+) -> Option<Deduction> {
+    let cover = indices(cover_mask);
+    if base.iter().any(|&i| masks[i as usize] & cover_mask == 0) {
+        return None;
+    }
+    let sashimi = base
+        .iter()
+        .any(|&i| (masks[i as usize] & cover_mask).count_ones() == 1);
+    if sashimi != (kind == Kind::Sashimi) {
+        return None;
+    }
+    let fins: Vec<(i32, i32)> = base
+        .iter()
+        .flat_map(|&i| {
+            indices(masks[i as usize] & !cover_mask)
+                .into_iter()
+                .map(move |j| crossing(by_rows, i, j))
+        })
+        .collect();
+    let rules = candidates.rules();
+    let fin_block = House::Block(rules.block(fins[0].0, fins[0].1));
+    if !fins.iter().all(|&(x, y)| rules.contains(fin_block, x, y)) {
+        return None;
+    }
+    let eliminations: Vec<(i32, i32, i32)> = cover
+        .iter()
+        .flat_map(|&j| (0..candidates.size()).map(move |i| (i, j)))
+        .filter(|(i, _)| !base.contains(i))
+        .map(|(i, j)| crossing(by_rows, i, j))
+        .filter(|&(x, y)| rules.contains(fin_block, x, y) && candidates.is_open(x, y, digit))
+        .map(|(x, y)| (x, y, digit))
+        .collect();
+    if eliminations.is_empty() {
+        return None;
+    }
+    let cells: Vec<(i32, i32)> = base
+        .iter()
+        .flat_map(|&i| {
+            indices(masks[i as usize])
+                .into_iter()
+                .map(move |j| crossing(by_rows, i, j))
+        })
+        .collect();
+    Some(Deduction {
+        technique,
+        description: format!(
+            "{} in {} only fits {} or the fins {}",
+            digit,
+            line_list(by_rows, base),
+            line_list(!by_rows, &cover),
+            cell_list(&fins)
+        ),
+        cells,
+        placements: Vec::new(),
+        eliminations,
+    })
+}
@@ -0,0 +1,472 @@
+//! Unique and avoidable rectangles and BUG+1, which only hold for puzzles
+//! with a single solution.
+//!
+//! Two digits filling the corners of a rectangle spanning two blocks could
+//! be swapped without breaking any house, unless one of the corners is a
+//! given. A puzzle with a single solution can never end up in such a deadly
+//! pattern, so any candidate leading to one is false.
+
+use super::{
+    cell_list, combinations, digit_list, digits, eliminate_seeing_all, Candidates, Deduction,
+    Technique,
+};
+use crate::house::{cell_name, House};
+use crate::rules::Rules;
+
+/// The corners of every rectangle spanning exactly two blocks, ordered so
+/// that corners `i` and `3 - i` are diagonal.
+fn rectangles(rules: &Rules) -> Vec<[(i32, i32); 4]> {
+    let size = rules.shape().size();
+    let mut rectangles = Vec::new();
+    for y1 in 0..size {
+        for y2 in y1 + 1..size {
+            for x1 in 0..size {
+                for x2 in x1 + 1..size {
+                    let [a, b, c, d] = [
+                        rules.block(x1, y1),
+                        rules.block(x2, y1),
+                        rules.block(x1, y2),
+                        rules.block(x2, y2),
+                    ];
+                    if (a == b && c == d && a != c) || (a == c && b == d && a != b) {
+                        rectangles.push([(x1, y1), (x2, y1), (x1, y2), (x2, y2)]);
+                    }
+                }
+            }
+        }
+    }
+    rectangles
+}
+
+/// The houses containing both cells.
+fn shared_houses(rules: &Rules, a: (i32, i32), b: (i32, i32)) -> Vec<House> {
+    rules
+        .houses_of(a.0, a.1)
+        .iter()
+        .copied()
+        .filter(|&house| rules.contains(house, b.0, b.1))
+        .collect()
+}
+
+fn describe(corners: &[(i32, i32); 4], pair: u32) -> String {
+    format!(
+        "{} in {} would be deadly",
+        digit_list(pair),
+        cell_list(corners)
+    )
+}
+
+pub fn unique_rectangle(
+    candidates: &Candidates,
+    technique: Technique,
+    kind: u8,
+) -> Option<Deduction> {
+    for corners in rectangles(candidates.rules()) {
+        if corners.iter().any(|&(x, y)| candidates.is_placed(x, y)) {
+            continue;
+        }
+        let masks: Vec<u32> = corners
+            .iter()
+            .map(|&(x, y)| candidates.cell(x, y).possibles_mask())
+            .collect();
+        let common = masks.iter().fold(u32::MAX, |common, mask| common & mask);
+        for pair in combinations(&digits(common), 2) {
+            let pair_mask = (1 << (pair[0] - 1)) | (1 << (pair[1] - 1));
+            // The roof is the corners with candidates besides the pair.
+            let roof: Vec<usize> = (0..4).filter(|&i| masks[i] != pair_mask).collect();
+            let found = match kind {
+                1 => type_1(candidates, &corners, pair_mask, &roof),
+                2 | 5 => type_2_or_5(candidates, &corners, &masks, pair_mask, &roof, kind),
+                3 => type_3(candidates, &corners, &masks, pair_mask, &roof),
+                4 => type_4(candidates, &corners, pair_mask, &roof),
+                _ => type_6(candidates, &corners, pair_mask, &roof),
+            };
+            if let Some((cells, eliminations, reason)) = found {
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique,
+                        cells,
+                        placements: Vec::new(),
+                        eliminations,
+                        description: format!("{}, {}", describe(&corners, pair_mask), reason),
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The cells forming the pattern, the eliminations and why they follow.
+type Found = (Vec<(i32, i32)>, Vec<(i32, i32, i32)>, String);
+
+/// One corner has other candidates, so it cannot be either digit of the
+/// pair.
+fn type_1(
+    candidates: &Candidates,
+    corners: &[(i32, i32); 4],
+    pair: u32,
+    roof: &[usize],
+) -> Option<Found> {
+    if roof.len() != 1 {
+        return None;
+    }
+    let (x, y) = corners[roof[0]];
+    let eliminations = digits(pair)
+        .into_iter()
+        .filter(|&digit| candidates.cell(x, y).is_possible(digit))
+        .map(|digit| (x, y, digit))
+        .collect();
+    Some((
+        corners.to_vec(),
+        eliminations,
+        format!("so {} is something else", cell_name(x, y)),
+    ))
+}
+
+/// The corners with other candidates all have the same single extra digit,
+/// which one of them must be. Type 2 has two corners in a line, type 5 any
+/// other layout.
+fn type_2_or_5(
+    candidates: &Candidates,
+    corners: &[(i32, i32); 4],
+    masks: &[u32],
+    pair: u32,
+    roof: &[usize],
+    kind: u8,
+) -> Option<Found> {
+    let in_line = roof.len() == 2 && roof[0] + roof[1] != 3;
+    if roof.len() < 2 || roof.len() > 3 || in_line != (kind == 2) {
+        return None;
+    }
+    let extra = masks[roof[0]] & !pair;
+    if extra.count_ones() != 1 || roof.iter().any(|&i| masks[i] & !pair != extra) {
+        return None;
+    }
+    let digit = digits(extra)[0];
+    let roof_cells: Vec<(i32, i32)> = roof.iter().map(|&i| corners[i]).collect();
+    Some((
+        corners.to_vec(),
+        eliminate_seeing_all(candidates, digit, &roof_cells),
+        format!("so one of {} is {}", cell_list(&roof_cells), digit),
+    ))
+}
+
+/// Two corners in a line have other candidates, one of which is true. They
+/// act as a single cell with those candidates in a naked subset.
+fn type_3(
+    candidates: &Candidates,
+    corners: &[(i32, i32); 4],
+    masks: &[u32],
+    pair: u32,
+    roof: &[usize],
+) -> Option<Found> {
+    if roof.len() != 2 || roof[0] + roof[1] == 3 {
+        return None;
+    }
+    let extra = (masks[roof[0]] | masks[roof[1]]) & !pair;
+    if extra.count_ones() < 2 {
+        return None;
+    }
+    let (a, b) = (corners[roof[0]], corners[roof[1]]);
+    for house in shared_houses(candidates.rules(), a, b) {
+        let others: Vec<(i32, i32)> = candidates
+            .house_cells(house)
+            .filter(|&cell| cell != a && cell != b)
+            .filter(|&(x, y)| !candidates.is_placed(x, y))
+            .collect();
+        for size in 1..=3 {
+            for subset in combinations(&others, size) {
+                let mask = subset.iter().fold(extra, |mask, &(x, y)| {
+                    mask | candidates.cell(x, y).possibles_mask()
+                });
+                if mask.count_ones() as usize != size + 1 {
+                    continue;
+                }
+                let eliminations: Vec<(i32, i32, i32)> = others
+                    .iter()
+                    .filter(|cell| !subset.contains(cell))
+                    .flat_map(|&(x, y)| {
+                        digits(mask & candidates.cell(x, y).possibles_mask())
+                            .into_iter()
+                            .map(move |digit| (x, y, digit))
+                    })
+                    .collect();
+                if !eliminations.is_empty() {
+                    let mut cells = corners.to_vec();
+                    cells.extend(subset.iter().copied());
+                    return Some((
+                        cells,
+                        eliminations,
+                        format!(
+                            "so {} and {} with {} only contain {} in {}",
+                            cell_name(a.0, a.1),
+                            cell_name(b.0, b.1),
+                            cell_list(&subset),
+                            digit_list(mask),
+                            house
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Two corners in a line have other candidates and one digit of the pair is
+/// confined to them in a house. The other digit of the pair would complete
+/// the pattern there.
+fn type_4(
+    candidates: &Candidates,
+    corners: &[(i32, i32); 4],
+    pair: u32,
+    roof: &[usize],
+) -> Option<Found> {
+    if roof.len() != 2 || roof[0] + roof[1] == 3 {
+        return None;
+    }
+    let (a, b) = (corners[roof[0]], corners[roof[1]]);
+    let pair_digits = digits(pair);
+    for house in shared_houses(candidates.rules(), a, b) {
+        for (i, &digit) in pair_digits.iter().enumerate() {
+            let positions = candidates.positions(house, digit);
+            if positions.len() != 2 {
+                continue;
+            }
+            let other = pair_digits[1 - i];
+            return Some((
+                corners.to_vec(),
+                vec![(a.0, a.1, other), (b.0, b.1, other)],
+                format!(
+                    "{} in {} only fits {} and {}",
+                    digit,
+                    house,
+                    cell_name(a.0, a.1),
+                    cell_name(b.0, b.1)
+                ),
+            ));
+        }
+    }
+    None
+}
+
+/// Two diagonal corners have other candidates and one digit of the pair
+/// forms an X-Wing on the rectangle. That digit in those corners would put
+/// the other digit in the remaining two.
+fn type_6(
+    candidates: &Candidates,
+    corners: &[(i32, i32); 4],
+    pair: u32,
+    roof: &[usize],
+) -> Option<Found> {
+    if roof.len() != 2 || roof[0] + roof[1] != 3 {
+        return None;
+    }
+    let (x1, y1) = corners[0];
+    let (x2, y2) = corners[3];
+    let lines = [
+        House::Row(y1),
+        House::Row(y2),
+        House::Column(x1),
+        House::Column(x2),
+    ];
+    for digit in digits(pair) {
+        if lines
+            .iter()
+            .any(|&line| candidates.positions(line, digit).len() != 2)
+        {
+            continue;
+        }
+        let eliminations = roof
+            .iter()
+            .map(|&i| (corners[i].0, corners[i].1, digit))
+            .collect();
+        return Some((
+            corners.to_vec(),
+            eliminations,
+            format!(
+                "{} only fits the corners in rows {} and {} and columns {} and {}",
+                digit,
+                y1 + 1,
+                y2 + 1,
+                x1 + 1,
+                x2 + 1
+            ),
+        ));
+    }
+    None
+}
+
+/// A rectangle with deduced (not given) digits in some corners. Type 1 has
+/// three corners placed, so the last one cannot repeat the digit of the
+/// opposite corner. Type 2 has two diagonal corners placed with the same
+/// digit and the other two hold the same extra digit, one of which is true.
+pub fn avoidable_rectangle(candidates: &Candidates) -> Option<Deduction> {
+    let deduced = |(x, y): (i32, i32)| {
+        if candidates.is_placed(x, y) && !candidates.is_given(x, y) {
+            candidates.cell(x, y).solution()
+        } else {
+            None
+        }
+    };
+    for corners in rectangles(candidates.rules()) {
+        let solved: Vec<Option<i32>> = corners.iter().map(|&cell| deduced(cell)).collect();
+        for open in 0..4 {
+            let (x, y) = corners[open];
+            let placed: Vec<usize> = (0..4).filter(|&i| solved[i].is_some()).collect();
+            if placed.len() == 3 && !candidates.is_placed(x, y) {
+                let digit = solved[3 - open].unwrap();
+                let sides: Vec<i32> = placed
+                    .iter()
+                    .filter(|&&i| i != 3 - open)
+                    .map(|&i| solved[i].unwrap())
+                    .collect();
+                if sides[0] == sides[1]
+                    && sides[0] != digit
+                    && candidates.cell(x, y).is_possible(digit)
+                {
+                    return Some(Deduction {
+                        technique: Technique::AvoidableRectangle,
+                        cells: corners.to_vec(),
+                        placements: Vec::new(),
+                        eliminations: vec![(x, y, digit)],
+                        description: format!(
+                            "{}/{} in {} would be deadly",
+                            digit.min(sides[0]),
+                            digit.max(sides[0]),
+                            cell_list(&corners)
+                        ),
+                    });
+                }
+            }
+        }
+        // Type 2: one diagonal placed with the same digit, the other open.
+        for &(first, second) in &[(0, 3), (1, 2)] {
+            let digit = match (solved[first], solved[second]) {
+                (Some(a), Some(b)) if a == b => a,
+                _ => continue,
+            };
+            let (a, b) = (corners[3 - second], corners[3 - first]);
+            if candidates.is_placed(a.0, a.1) || candidates.is_placed(b.0, b.1) {
+                continue;
+            }
+            let (mask_a, mask_b) = (
+                candidates.cell(a.0, a.1).possibles_mask(),
+                candidates.cell(b.0, b.1).possibles_mask(),
+            );
+            if mask_a != mask_b || mask_a.count_ones() != 2 {
+                continue;
+            }
+            let others: Vec<i32> = digits(mask_a);
+            // Both open corners need a digit to swap with the placed one and
+            // one extra digit.
+            for (i, &swap) in others.iter().enumerate() {
+                let extra = others[1 - i];
+                if swap == digit || extra == digit {
+                    continue;
+                }
+                let eliminations = eliminate_seeing_all(candidates, extra, &[a, b]);
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique: Technique::AvoidableRectangle,
+                        cells: corners.to_vec(),
+                        placements: Vec::new(),
+                        eliminations,
+                        description: format!(
+                            "{}/{} in {} would be deadly, so one of {} and {} is {}",
+                            digit.min(swap),
+                            digit.max(swap),
+                            cell_list(&corners),
+                            cell_name(a.0, a.1),
+                            cell_name(b.0, b.1),
+                            extra
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Bivalue universal grave plus one: every open cell but one has two
+/// candidates and every digit is open twice in each house. With the
+/// remaining cell reduced to two candidates the puzzle would have two
+/// solutions, so it is the digit open three times in its houses.
+pub fn bug_plus_one(candidates: &Candidates) -> Option<Deduction> {
+    let mut extra_cell = None;
+    let shape = candidates.shape();
+    for i in 0..shape.num_cells() {
+        let (x, y) = shape.cell(i);
+        if candidates.is_placed(x, y) {
+            continue;
+        }
+        match candidates.cell(x, y).num_possibles() {
+            2 => {}
+            3 if extra_cell.is_none() => extra_cell = Some((x, y)),
+            _ => return None,
+        }
+    }
+    let (x, y) = extra_cell?;
+    let mut bug_digit = None;
+    for house in candidates.houses() {
+        for digit in 1..=candidates.size() {
+            let count = candidates.positions(house, digit).len();
+            if count == 3
+                && candidates.rules().contains(house, x, y)
+                && candidates.is_open(x, y, digit)
+            {
+                if bug_digit.is_some() && bug_digit != Some(digit) {
+                    return None;
+                }
+                bug_digit = Some(digit);
+            } else if count != 0 && count != 2 {
+                return None;
+            }
+        }
+    }
+    let digit = bug_digit?;
+    Some(Deduction {
+        technique: Technique::BugPlusOne,
+        cells: vec![(x, y)],
+        placements: vec![(x, y, digit)],
+        eliminations: Vec::new(),
+        description: format!(
+            "{} is the only cell with three candidates and {} is open three times in its houses",
+            cell_name(x, y),
+            digit
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cell::Cell;
+    use crate::house::Shape;
+
+    /// A rectangle on 10 and 11 in a 16x16 grid, with 12 as well in its
+    /// last corner.
+    #[test]
+    fn type_1_above_nine() {
+        let shape = Shape::new(4, 4);
+        let rules = Rc::new(Rules::new(shape, &[], &[]));
+        let mut cells: Vec<Cell> = (0..shape.num_cells())
+            .map(|_| Cell::with_digits(16))
+            .collect();
+        let corners = [(0, 0), (1, 0), (0, 4), (1, 4)];
+        for (i, &(x, y)) in corners.iter().enumerate() {
+            let keep: &[i32] = if i == 3 { &[10, 11, 12] } else { &[10, 11] };
+            let cell = &mut cells[shape.index(x, y)];
+            for digit in (1..=16).filter(|digit| !keep.contains(digit)) {
+                cell.eliminate_possible(digit).unwrap();
+            }
+        }
+        let candidates = Candidates::from_cells(rules, cells);
+        let deduction = Technique::UniqueRectangleType1.find(&candidates).unwrap();
+        assert_eq!(deduction.eliminations, vec![(1, 4, 10), (1, 4, 11)]);
+    }
+}
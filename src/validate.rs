@@ -0,0 +1,195 @@
+use std::fmt;
+
+use crate::house::{cell_name, House};
+use crate::rules::{Rules, Variant};
+
+/// Two hints with the same digit that see each other, or with consecutive
+/// digits next to each other in a non-consecutive puzzle, or the hints of a
+/// killer cage that cannot add up to its sum.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub first: (i32, i32),
+    pub second: (i32, i32),
+    /// The digit of the first hint.
+    pub digit: i32,
+    /// The houses both hints are in.
+    pub houses: Vec<House>,
+    /// The variant broken by hints that share no house.
+    pub variant: Option<Variant>,
+    /// The index of the killer cage holding hints that share no house.
+    pub cage: Option<usize>,
+    /// The hints of the cage when they rule out its sum, empty for a
+    /// conflict between two hints. `first` and `second` are then the first
+    /// and last of them, the same cell if there is only one.
+    pub cage_hints: Vec<(i32, i32)>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let first = cell_name(self.first.0, self.first.1);
+        let second = cell_name(self.second.0, self.second.1);
+        if let (Some(cage), false) = (self.cage, self.cage_hints.is_empty()) {
+            let hints: Vec<String> = self
+                .cage_hints
+                .iter()
+                .map(|&(x, y)| cell_name(x, y))
+                .collect();
+            return write!(
+                f,
+                "{} cannot add up to the sum of cage {}",
+                hints.join(", "),
+                cage + 1
+            );
+        }
+        if let Some(cage) = self.cage {
+            return write!(
+                f,
+                "{} and {} are both {} in cage {}",
+                first,
+                second,
+                self.digit,
+                cage + 1
+            );
+        }
+        match self.variant {
+            Some(Variant::AntiKnight) => {
+                return write!(
+                    f,
+                    "{} and {} are both {} a knight's move apart",
+                    first, second, self.digit
+                )
+            }
+            Some(Variant::AntiKing) => {
+                return write!(
+                    f,
+                    "{} and {} are both {} a king's move apart",
+                    first, second, self.digit
+                )
+            }
+            Some(Variant::NonConsecutive) => {
+                return write!(
+                    f,
+                    "{} and {} hold consecutive digits next to each other",
+                    first, second
+                )
+            }
+            _ => {}
+        }
+        write!(f, "{} and {} are both {} in ", first, second, self.digit)?;
+        for (i, house) in self.houses.iter().enumerate() {
+            if i > 0 {
+                write!(f, " and ")?;
+            }
+            write!(f, "{}", house)?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds every pair of hints that contradict each other under `rules`,
+/// given hints in row-major order. A puzzle without conflicts can still be
+/// unsolvable.
+pub fn find_conflicts(rules: &Rules, hints: &[Option<i32>]) -> Vec<Conflict> {
+    let shape = rules.shape();
+    let mut conflicts = Vec::new();
+    for (i, first) in hints.iter().enumerate() {
+        let digit = match first {
+            Some(digit) => *digit,
+            None => continue,
+        };
+        let first = shape.cell(i);
+        for (j, second) in hints.iter().enumerate().skip(i + 1) {
+            let second_digit = match second {
+                Some(second_digit) => *second_digit,
+                None => continue,
+            };
+            let second = shape.cell(j);
+            if second_digit != digit {
+                if (second_digit - digit).abs() == 1
+                    && rules.neighbours(first.0, first.1).contains(&second)
+                {
+                    conflicts.push(Conflict {
+                        first,
+                        second,
+                        digit,
+                        houses: Vec::new(),
+                        variant: Some(Variant::NonConsecutive),
+                        cage: None,
+                        cage_hints: Vec::new(),
+                    });
+                }
+                continue;
+            }
+            if !rules.sees(first, second) {
+                continue;
+            }
+            let second_houses = rules.houses_of(second.0, second.1);
+            let houses: Vec<House> = rules
+                .houses_of(first.0, first.1)
+                .iter()
+                .filter(|house| second_houses.contains(house))
+                .copied()
+                .collect();
+            let cage = rules
+                .cages()
+                .iter()
+                .position(|cage| cage.cells.contains(&first) && cage.cells.contains(&second))
+                .filter(|_| houses.is_empty());
+            let variant = if !houses.is_empty() || cage.is_some() {
+                None
+            } else if (first.0 - second.0).abs() == 1 && (first.1 - second.1).abs() == 1 {
+                Some(Variant::AntiKing)
+            } else {
+                Some(Variant::AntiKnight)
+            };
+            conflicts.push(Conflict {
+                first,
+                second,
+                digit,
+                houses,
+                variant,
+                cage,
+                cage_hints: Vec::new(),
+            });
+        }
+    }
+    conflicts.extend(cage_sum_conflicts(rules, hints));
+    conflicts
+}
+
+/// The cages whose hints leave no combination of digits adding up to the
+/// sum, whatever the other cells hold.
+fn cage_sum_conflicts(rules: &Rules, hints: &[Option<i32>]) -> Vec<Conflict> {
+    let shape = rules.shape();
+    let all = u32::MAX >> (32 - shape.size());
+    let mut conflicts = Vec::new();
+    for (i, cage) in rules.cages().iter().enumerate() {
+        let cage_hints: Vec<(i32, i32)> = cage
+            .cells
+            .iter()
+            .filter(|&&(x, y)| hints[shape.index(x, y)].is_some())
+            .copied()
+            .collect();
+        let possibles: Vec<u32> = cage
+            .cells
+            .iter()
+            .map(|&(x, y)| match hints[shape.index(x, y)] {
+                Some(digit) => 1 << (digit - 1),
+                None => all,
+            })
+            .collect();
+        if cage_hints.is_empty() || !rules.cage_possibles(i, &possibles).contains(&0) {
+            continue;
+        }
+        conflicts.push(Conflict {
+            first: cage_hints[0],
+            second: cage_hints[cage_hints.len() - 1],
+            digit: hints[shape.index(cage_hints[0].0, cage_hints[0].1)].unwrap_or_default(),
+            houses: Vec::new(),
+            variant: None,
+            cage: Some(i),
+            cage_hints,
+        });
+    }
+    conflicts
+}
@@ -1,13 +1,253 @@
-use std::fs::File;
 use std::io::prelude::*;
+use std::str::FromStr;
+use std::time::Duration;
 
-pub fn write(filename: &str, solution: &Vec<i32>) -> std::io::Result<()> {
-    let mut file = File::options().append(true).open(filename)?;
-    let solution_string = solution
-        .iter()
-        .map(|digit| digit.to_string())
-        .collect::<String>()
-        + "\n";
-    file.write_all(solution_string.as_bytes())?;
+use serde_json::json;
+
+use crate::house::{digit_char, Shape};
+use crate::multigrid::Layout;
+use crate::reader::Puzzle;
+use crate::solver::{SolveOutcome, SolveStats};
+
+/// Text formats solutions can be written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// The 81 digits of the solution on one line, or `unsolvable`, `multiple`
+    /// or `aborted` if there is no unique solution. Digits above 9 are
+    /// written as letters. The grids of a layout are separated by spaces.
+    Line,
+    /// The grid with block separators, preceded by the case number. A
+    /// layout is drawn as a whole with blanks where no grid is.
+    Grid,
+    /// `puzzle,solution` pairs with a header, the solution is empty if there
+    /// is no unique solution. Only puzzles without declarations fit.
+    Csv,
+    /// One JSON object per case with the puzzle, solution and solve metadata.
+    /// The puzzle starts with the lines declaring its rules, if any.
+    JsonLines,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name {
+            "line" => Ok(Format::Line),
+            "grid" => Ok(Format::Grid),
+            "csv" => Ok(Format::Csv),
+            "jsonl" => Ok(Format::JsonLines),
+            _ => Err(format!(
+                "unknown format '{}', expected one of line, grid, csv, jsonl",
+                name
+            )),
+        }
+    }
+}
+
+/// Everything known about one solved (or unsolved) case.
+pub struct Record<'a> {
+    pub case: usize,
+    pub puzzle: &'a Puzzle,
+    pub outcome: &'a SolveOutcome,
+    pub stats: &'a SolveStats,
+    pub elapsed: Duration,
+}
+
+fn outcome_name(outcome: &SolveOutcome) -> &'static str {
+    match outcome {
+        SolveOutcome::Solved(_) => "solved",
+        SolveOutcome::Unsolvable => "unsolvable",
+        SolveOutcome::MultipleSolutions(_, _) => "multiple",
+        SolveOutcome::Aborted => "aborted",
+    }
+}
+
+fn digits_string(digits: &[i32]) -> String {
+    digits.iter().map(|&digit| digit_char(digit)).collect()
+}
+
+fn solution_string(puzzle: &Puzzle, solution: &[i32]) -> String {
+    match &puzzle.layout {
+        Some(layout) => (0..layout.origins().len())
+            .map(|g| digits_string(&layout.grid_values(g, solution)))
+            .collect::<Vec<String>>()
+            .join(" "),
+        None => digits_string(solution),
+    }
+}
+
+fn grid_string(shape: Shape, solution: &[i32]) -> String {
+    let size = shape.size() as usize;
+    let (width, height) = (shape.box_width as usize, shape.box_height as usize);
+    let separator = vec!["-".repeat(width * 2 - 1); height].join("-+-");
+    let mut grid = String::new();
+    for (y, row) in solution.chunks(size).enumerate() {
+        if y > 0 && y % height == 0 {
+            grid += &separator;
+            grid += "\n";
+        }
+        for (x, &digit) in row.iter().enumerate() {
+            if x > 0 && x % width == 0 {
+                grid += "| ";
+            }
+            grid.push(digit_char(digit));
+            grid += if x == size - 1 { "\n" } else { " " };
+        }
+    }
+    grid
+}
+
+/// The grid of a jigsaw sudoku, with `|` between cells of different regions
+/// and `-` under cells whose region ends there.
+fn jigsaw_grid_string(shape: Shape, regions: &[i32], solution: &[i32]) -> String {
+    let size = shape.size() as usize;
+    let mut grid = String::new();
+    for (y, row) in solution.chunks(size).enumerate() {
+        if y > 0 {
+            let separator: String = (0..size)
+                .map(|x| {
+                    if regions[(y - 1) * size + x] != regions[y * size + x] {
+                        "- "
+                    } else {
+                        "  "
+                    }
+                })
+                .collect();
+            if !separator.trim().is_empty() {
+                grid += separator.trim_end();
+                grid += "\n";
+            }
+        }
+        for (x, &digit) in row.iter().enumerate() {
+            grid.push(digit_char(digit));
+            grid += if x == size - 1 {
+                "\n"
+            } else if regions[y * size + x] != regions[y * size + x + 1] {
+                "|"
+            } else {
+                " "
+            };
+        }
+    }
+    grid
+}
+
+/// The canvas of a layout with block separators between the blocks of its
+/// grids and blanks elsewhere.
+fn layout_grid_string(layout: &Layout, solution: &[i32]) -> String {
+    let shape = layout.shape();
+    let (width, height) = (shape.box_width, shape.box_height);
+    let mut grid = String::new();
+    for y in 0..layout.height() {
+        if y > 0 && y % height == 0 {
+            let mut separator = String::new();
+            let mut previous = false;
+            for x in (0..layout.width()).step_by(width as usize) {
+                let covered = layout.contains(x, y - 1) || layout.contains(x, y);
+                if x > 0 {
+                    separator += if previous && covered { "-+-" } else { "   " };
+                }
+                let segment = if covered { "-" } else { " " };
+                separator += &segment.repeat(width as usize * 2 - 1);
+                previous = covered;
+            }
+            grid += separator.trim_end();
+            grid += "\n";
+        }
+        let mut row = String::new();
+        for x in 0..layout.width() {
+            if x > 0 && x % width == 0 {
+                row += if layout.contains(x - 1, y) && layout.contains(x, y) {
+                    "| "
+                } else {
+                    "  "
+                };
+            }
+            if layout.contains(x, y) {
+                row.push(digit_char(solution[layout.index(x, y)]));
+            } else {
+                row.push(' ');
+            }
+            row.push(' ');
+        }
+        grid += row.trim_end();
+        grid += "\n";
+    }
+    grid
+}
+
+/// Fails if `format` cannot hold the puzzle, CSV has no room for the lines
+/// declaring its rules.
+pub fn check(format: Format, puzzle: &Puzzle) -> Result<(), String> {
+    if format == Format::Csv && !puzzle.declarations().is_empty() {
+        return Err(
+            "csv cannot hold the declarations of the puzzle, use jsonl instead".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Writes whatever precedes the first record, only CSV has a header.
+pub fn write_header(output: &mut dyn Write, format: Format) -> std::io::Result<()> {
+    if format == Format::Csv {
+        output.write_all(b"puzzle,solution\n")?;
+    }
     Ok(())
 }
+
+/// Writes a record, failing with `InvalidInput` if the format cannot hold
+/// its puzzle, see [`check`].
+pub fn write(output: &mut dyn Write, format: Format, record: &Record) -> std::io::Result<()> {
+    check(format, record.puzzle)
+        .map_err(|message| std::io::Error::new(std::io::ErrorKind::InvalidInput, message))?;
+    let solution = record.outcome.solution();
+    match format {
+        Format::Line => match solution {
+            Some(solution) => writeln!(output, "{}", solution_string(record.puzzle, solution)),
+            None => writeln!(output, "{}", outcome_name(record.outcome)),
+        },
+        Format::Grid => match solution {
+            Some(solution) => writeln!(
+                output,
+                "Case {}\n{}",
+                record.case,
+                match (&record.puzzle.layout, &record.puzzle.regions) {
+                    (Some(layout), _) => layout_grid_string(layout, solution),
+                    (None, Some(regions)) => {
+                        jigsaw_grid_string(record.puzzle.shape, regions, solution)
+                    }
+                    (None, None) => grid_string(record.puzzle.shape, solution),
+                }
+            ),
+            None => writeln!(
+                output,
+                "Case {}: {}\n",
+                record.case,
+                outcome_name(record.outcome)
+            ),
+        },
+        Format::Csv => writeln!(
+            output,
+            "{},{}",
+            record.puzzle,
+            solution
+                .map(|solution| solution_string(record.puzzle, solution))
+                .unwrap_or_default()
+        ),
+        Format::JsonLines => {
+            let object = json!({
+                "case": record.case,
+                "puzzle": record.puzzle.to_string(),
+                "solution": solution.map(|solution| solution_string(record.puzzle, solution)),
+                "outcome": outcome_name(record.outcome),
+                "time_ms": record.elapsed.as_secs_f64() * 1000.0,
+                "eliminations": record.stats.eliminations,
+                "deductions": record.stats.deductions,
+                "guesses": record.stats.guesses,
+                "backtracks": record.stats.backtracks,
+                "max_depth": record.stats.max_depth,
+            });
+            writeln!(output, "{}", object)
+        }
+    }
+}